@@ -0,0 +1,318 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use time::format_description::BorrowedFormatItem;
+use time::macros::format_description;
+use time::OffsetDateTime;
+use valence::prelude::*;
+use valence::protocol::packets::play::DisconnectS2c;
+use valence::protocol::WritePacket;
+
+const WHITELIST_PATH: &str = "whitelist.json";
+const BANS_PATH: &str = "banned-players.json";
+const OPS_PATH: &str = "ops.json";
+
+/// How vanilla writes the dates in the ban list.
+const DATE_FORMAT: &[BorrowedFormatItem] = format_description!(
+    "[year]-[month]-[day] [hour]:[minute]:[second] [offset_hour sign:mandatory][offset_minute]"
+);
+
+/// Entries added while the player was offline only have a name, and get
+/// their UUID once that player first logs in.
+#[derive(Serialize, Deserialize, Clone)]
+struct WhitelistEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct BanEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<Uuid>,
+    name: String,
+    created: String,
+    source: String,
+    /// A date, or `forever`.
+    expires: String,
+    reason: String,
+}
+
+impl BanEntry {
+    fn expired(&self) -> bool {
+        match OffsetDateTime::parse(&self.expires, DATE_FORMAT) {
+            Ok(expires) => expires <= OffsetDateTime::now_utc(),
+            Err(_) => false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct OpEntry {
+    uuid: Uuid,
+    name: String,
+    level: u8,
+    bypasses_player_limit: bool,
+}
+
+struct Lists {
+    whitelist_enabled: bool,
+    whitelist: Vec<WhitelistEntry>,
+    bans: Vec<BanEntry>,
+    ops: Vec<OpEntry>,
+}
+
+impl Lists {
+    /// Fills in the UUID of entries added by name only.
+    fn claim_entries(&mut self, player_id: Uuid, username: &str) {
+        let unclaimed = |uuid: Option<Uuid>, name: &str| {
+            uuid.is_none() && name.eq_ignore_ascii_case(username)
+        };
+
+        let mut claimed = false;
+        for entry in &mut self.whitelist {
+            if unclaimed(entry.uuid, &entry.name) {
+                entry.uuid = Some(player_id);
+                entry.name = username.to_owned();
+                claimed = true;
+            }
+        }
+        if claimed {
+            write_list(Path::new(WHITELIST_PATH), &self.whitelist);
+        }
+
+        let mut claimed = false;
+        for ban in &mut self.bans {
+            if unclaimed(ban.uuid, &ban.name) {
+                ban.uuid = Some(player_id);
+                ban.name = username.to_owned();
+                claimed = true;
+            }
+        }
+        if claimed {
+            write_list(Path::new(BANS_PATH), &self.bans);
+        }
+    }
+}
+
+/// The whitelist, ban list and operator list, stored in the same JSON files
+/// as on a vanilla server. Shared with the login callback, which runs outside
+/// of the ECS.
+#[derive(Resource, Clone)]
+pub struct AccessLists(Arc<RwLock<Lists>>);
+
+impl AccessLists {
+    /// Loads the lists from the working directory. Missing files are empty
+    /// lists.
+    pub fn load(whitelist_enabled: bool) -> Result<Self, String> {
+        Ok(Self(Arc::new(RwLock::new(Lists {
+            whitelist_enabled,
+            whitelist: read_list(Path::new(WHITELIST_PATH))?,
+            bans: read_list(Path::new(BANS_PATH))?,
+            ops: read_list(Path::new(OPS_PATH))?,
+        }))))
+    }
+
+    /// Why the player may not join, if they may not.
+    pub fn check_login(&self, player_id: Uuid, username: &str) -> Result<(), String> {
+        let mut lists = self.0.write().unwrap();
+        lists.claim_entries(player_id, username);
+
+        if let Some(ban) = lists
+            .bans
+            .iter()
+            .find(|ban| ban.uuid == Some(player_id) && !ban.expired())
+        {
+            return Err(format!("You are banned from this server.\nReason: {}", ban.reason));
+        }
+
+        let whitelisted = lists
+            .whitelist
+            .iter()
+            .any(|entry| entry.uuid == Some(player_id))
+            || lists.ops.iter().any(|op| op.uuid == player_id);
+        if lists.whitelist_enabled && !whitelisted {
+            return Err("You are not whitelisted on this server.".to_owned());
+        }
+
+        Ok(())
+    }
+
+    pub fn set_whitelist_enabled(&self, enabled: bool) {
+        self.0.write().unwrap().whitelist_enabled = enabled;
+    }
+
+    /// Returns false if the player is already on the whitelist. Players who
+    /// aren't online are added by name.
+    pub fn whitelist(&self, player_id: Option<Uuid>, name: &str) -> bool {
+        let mut lists = self.0.write().unwrap();
+        if lists
+            .whitelist
+            .iter()
+            .any(|entry| same_player(entry.uuid, &entry.name, player_id, name))
+        {
+            return false;
+        }
+
+        lists.whitelist.push(WhitelistEntry {
+            uuid: player_id,
+            name: name.to_owned(),
+        });
+        write_list(Path::new(WHITELIST_PATH), &lists.whitelist);
+
+        true
+    }
+
+    /// Returns false if nobody by that name is on the whitelist.
+    pub fn unwhitelist(&self, name: &str) -> bool {
+        let mut lists = self.0.write().unwrap();
+        let before = lists.whitelist.len();
+        lists
+            .whitelist
+            .retain(|entry| !entry.name.eq_ignore_ascii_case(name));
+        if lists.whitelist.len() == before {
+            return false;
+        }
+
+        write_list(Path::new(WHITELIST_PATH), &lists.whitelist);
+        true
+    }
+
+    pub fn whitelisted(&self) -> Vec<String> {
+        let lists = self.0.read().unwrap();
+
+        lists
+            .whitelist
+            .iter()
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+
+    /// Bans the player for good, replacing an earlier ban. Players who aren't
+    /// online are banned by name.
+    pub fn ban(&self, player_id: Option<Uuid>, name: &str, reason: &str, source: &str) {
+        let mut lists = self.0.write().unwrap();
+        lists
+            .bans
+            .retain(|ban| !same_player(ban.uuid, &ban.name, player_id, name));
+
+        lists.bans.push(BanEntry {
+            uuid: player_id,
+            name: name.to_owned(),
+            created: OffsetDateTime::now_utc()
+                .format(DATE_FORMAT)
+                .unwrap_or_default(),
+            source: source.to_owned(),
+            expires: "forever".to_owned(),
+            reason: reason.to_owned(),
+        });
+        write_list(Path::new(BANS_PATH), &lists.bans);
+    }
+
+    /// Returns false if nobody by that name is banned.
+    pub fn pardon(&self, name: &str) -> bool {
+        let mut lists = self.0.write().unwrap();
+        let before = lists.bans.len();
+        lists
+            .bans
+            .retain(|ban| !ban.name.eq_ignore_ascii_case(name));
+        if lists.bans.len() == before {
+            return false;
+        }
+
+        write_list(Path::new(BANS_PATH), &lists.bans);
+        true
+    }
+
+    pub fn is_op(&self, player_id: Uuid) -> bool {
+        self.0
+            .read()
+            .unwrap()
+            .ops
+            .iter()
+            .any(|op| op.uuid == player_id)
+    }
+
+    /// Returns false if the player already is an operator.
+    pub fn op(&self, player_id: Uuid, name: &str) -> bool {
+        let mut lists = self.0.write().unwrap();
+        if lists.ops.iter().any(|op| op.uuid == player_id) {
+            return false;
+        }
+
+        lists.ops.push(OpEntry {
+            uuid: player_id,
+            name: name.to_owned(),
+            level: 4,
+            bypasses_player_limit: false,
+        });
+        write_list(Path::new(OPS_PATH), &lists.ops);
+
+        true
+    }
+
+    /// Returns the UUID of the operator by that name, if there was one.
+    pub fn deop(&self, name: &str) -> Option<Uuid> {
+        let mut lists = self.0.write().unwrap();
+        let index = lists
+            .ops
+            .iter()
+            .position(|op| op.name.eq_ignore_ascii_case(name))?;
+        let op = lists.ops.remove(index);
+
+        write_list(Path::new(OPS_PATH), &lists.ops);
+        Some(op.uuid)
+    }
+}
+
+/// Whether an entry is for the player, by UUID if both are known, or else by
+/// name.
+fn same_player(uuid: Option<Uuid>, name: &str, player_id: Option<Uuid>, username: &str) -> bool {
+    match (uuid, player_id) {
+        (Some(uuid), Some(player_id)) => uuid == player_id,
+        _ => name.eq_ignore_ascii_case(username),
+    }
+}
+
+fn read_list<T: DeserializeOwned>(path: &Path) -> Result<Vec<T>, String> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Failed to read `{}`: {e}.", path.display())),
+    };
+
+    serde_json::from_str(&json).map_err(|e| format!("Invalid `{}`: {e}.", path.display()))
+}
+
+fn write_list<T: Serialize>(path: &Path, list: &[T]) {
+    let result = serde_json::to_string_pretty(list)
+        .map_err(|e| e.to_string())
+        .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+
+    if let Err(e) = result {
+        eprintln!("failed to write {}: {e}", path.display());
+    }
+}
+
+/// A player being disconnected. They are only dropped on the next tick, so
+/// that the reason reaches them first.
+#[derive(Component)]
+pub struct Kicked;
+
+pub fn kick(commands: &mut Commands, player: Entity, client: &mut Client, reason: Text) {
+    client.write_packet(&DisconnectS2c {
+        reason: reason.into_cow_text(),
+    });
+    commands.entity(player).insert(Kicked);
+}
+
+pub fn disconnect_kicked_players(kicked: Query<Entity, With<Kicked>>, mut commands: Commands) {
+    for entity in &kicked {
+        commands.entity(entity).remove::<Client>();
+    }
+}
@@ -1,11 +1,116 @@
+use std::collections::HashMap;
+
 use rand::{rngs::SmallRng, Rng, SeedableRng};
 use valence::{
-    block::{PropName, PropValue},
+    block::{BlockKind, PropName, PropValue},
     prelude::BiomeId,
     registry::RegistryIdx,
     BlockState,
 };
 
+/// The biomes with their own built-in palette, by their index minus one.
+const BIOMES: [&str; 63] = [
+    "plains",
+    "sunflower_plains",
+    "snowy_plains",
+    "ice_spikes",
+    "desert",
+    "swamp",
+    "mangrove_swamp",
+    "forest",
+    "flower_forest",
+    "birch_forest",
+    "dark_forest",
+    "old_growth_birch_forest",
+    "old_growth_pine_taiga",
+    "old_growth_spruce_taiga",
+    "taiga",
+    "snowy_taiga",
+    "savanna",
+    "savanna_plateau",
+    "windswept_hills",
+    "windswept_gravelly_hills",
+    "windswept_forest",
+    "windswept_savanna",
+    "jungle",
+    "sparse_jungle",
+    "bamboo_jungle",
+    "badlands",
+    "eroded_badlands",
+    "wooded_badlands",
+    "meadow",
+    "cherry_grove",
+    "grove",
+    "snowy_slopes",
+    "frozen_peaks",
+    "jagged_peaks",
+    "stony_peaks",
+    "river",
+    "frozen_river",
+    "beach",
+    "snowy_beach",
+    "stony_shore",
+    "warm_ocean",
+    "lukewarm_ocean",
+    "deep_lukewarm_ocean",
+    "ocean",
+    "deep_ocean",
+    "cold_ocean",
+    "deep_cold_ocean",
+    "frozen_ocean",
+    "deep_frozen_ocean",
+    "mushroom_fields",
+    "dripstone_caves",
+    "lush_caves",
+    "deep_dark",
+    "nether_wastes",
+    "warped_forest",
+    "crimson_forest",
+    "soul_sand_valley",
+    "basalt_deltas",
+    "the_end",
+    "end_highlands",
+    "end_midlands",
+    "small_end_islands",
+    "end_barrens",
+];
+
+/// Blocks platforms are built from in some biomes, replacing the built-in
+/// palettes there.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Palettes(HashMap<usize, Vec<BlockState>>);
+
+impl Palettes {
+    /// Parses palettes given as biome names mapped to block names.
+    pub fn parse(palettes: &HashMap<String, Vec<String>>) -> Result<Self, String> {
+        let mut parsed = HashMap::new();
+
+        for (biome, blocks) in palettes {
+            let index = BIOMES
+                .iter()
+                .position(|name| name == biome)
+                .ok_or_else(|| format!("unknown biome `{biome}` in palettes"))?;
+
+            let blocks = blocks
+                .iter()
+                .map(|block| {
+                    BlockKind::from_str(block)
+                        .map(|kind| kind.to_state())
+                        .ok_or_else(|| format!("unknown block `{block}` in the {biome} palette"))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            if blocks.is_empty() {
+                return Err(format!("the {biome} palette is empty"));
+            }
+
+            parsed.insert(index + 1, blocks);
+        }
+
+        Ok(Self(parsed))
+    }
+}
+
 // Create a random choice helper as we'll use that a lot
 fn random_choice<T>(choices: &[T]) -> T
 where
@@ -15,8 +120,12 @@ where
     choices[rng.gen_range(0..choices.len())]
 }
 
-pub fn choose_block(biome: Option<BiomeId>) -> BlockState {
+pub fn choose_block(biome: Option<BiomeId>, palettes: &Palettes) -> BlockState {
     biome.map_or(BlockState::POLISHED_DIORITE, |biome| {
+        if let Some(palette) = palettes.0.get(&biome.to_index()) {
+            return random_choice(palette);
+        }
+
         match biome.to_index() {
             1 => {
                 // plains
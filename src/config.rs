@@ -0,0 +1,616 @@
+use std::collections::HashMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use valence::network::ConnectionMode;
+use valence::prelude::*;
+
+use crate::block_chooser::Palettes;
+use crate::difficulty::Preset;
+use crate::instance::CourseConfig;
+use crate::jump::JumpKind;
+use crate::parkour::SkipPolicy;
+use crate::Cli;
+
+/// Read when no config file is given, if it exists.
+const DEFAULT_CONFIG_PATH: &str = "parkour.toml";
+
+const DEFAULT_SPAWN: [i32; 3] = [0, 196, 0];
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// How many ticks to wait between checking whether the config file changed.
+const WATCH_INTERVAL: u32 = 20;
+
+/// The config file as written. Everything is optional, missing values fall
+/// back to the defaults.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    server: ServerSection,
+    world: WorldSection,
+    gameplay: GameplaySection,
+    messages: Messages,
+    rcon: RconSection,
+    http: HttpSection,
+    /// Base weights of jump kinds by name, e.g. `long_flat = 0.5`.
+    jumps: HashMap<String, f64>,
+    /// Blocks to build platforms from by biome name.
+    palettes: HashMap<String, Vec<String>>,
+    courses: Vec<CourseSection>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct ServerSection {
+    address: Option<IpAddr>,
+    port: Option<u16>,
+    max_players: Option<usize>,
+    queue_size: Option<usize>,
+    whitelist: Option<bool>,
+    motd: Option<String>,
+    favicon: Option<PathBuf>,
+    connection_mode: Option<String>,
+    /// Online mode only: turn away players connecting from a different
+    /// address than the one they authenticated from.
+    prevent_proxy_connections: Option<bool>,
+    /// The forwarding secret shared with a Velocity proxy.
+    velocity_secret: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RconSection {
+    enabled: bool,
+    port: Option<u16>,
+    password: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct HttpSection {
+    enabled: bool,
+    port: Option<u16>,
+    /// Needed to use the endpoints that change anything.
+    token: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct WorldSection {
+    path: Option<PathBuf>,
+    spawn: Option<[i32; 3]>,
+    preload_radius: Option<i32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct GameplaySection {
+    seed: Option<u64>,
+    difficulty: Option<String>,
+    lookahead: Option<i32>,
+    adaptive: Option<bool>,
+    private: Option<bool>,
+    skip_policy: Option<String>,
+    void_rate: Option<f64>,
+    crumble: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CourseSection {
+    name: String,
+    difficulty: Option<String>,
+    length: Option<i32>,
+    seed: Option<u64>,
+}
+
+/// Messages players get that can be changed in the config file.
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Messages {
+    /// Sent in chat when a player joins, above the list of courses.
+    pub welcome: String,
+    /// Shown when a player is let onto the course they joined.
+    pub course_ready: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            welcome: "Pick a course to play:".to_owned(),
+            course_ready: "Good luck!".to_owned(),
+        }
+    }
+}
+
+/// Where the RCON listener runs and the password clients log in with.
+#[derive(Clone, PartialEq)]
+pub struct RconSettings {
+    pub port: u16,
+    pub password: String,
+}
+
+/// Where the HTTP API runs and the token that authorizes changes. Without a
+/// token, it is read-only.
+#[derive(Clone, PartialEq)]
+pub struct HttpSettings {
+    pub port: u16,
+    pub token: Option<String>,
+}
+
+/// The server settings, from the config file with the command line flags
+/// taking precedence.
+#[derive(Resource)]
+pub struct Settings {
+    pub world: PathBuf,
+    pub spawn: BlockPos,
+    /// Chunks this far around the spawn are loaded before anyone joins.
+    pub preload_radius: i32,
+    pub address: SocketAddr,
+    pub max_players: usize,
+    /// How many players can wait for a free slot. Nobody waits if zero.
+    pub queue_size: usize,
+    /// Only let in players on the whitelist.
+    pub whitelist: bool,
+    pub motd: String,
+    pub favicon: Vec<u8>,
+    pub connection_mode: ConnectionMode,
+    /// Remote administration over RCON. Off if not set.
+    pub rcon: Option<RconSettings>,
+    /// The HTTP status and admin API. Off if not set.
+    pub http: Option<HttpSettings>,
+    /// The seed of courses that don't set their own. Random if not set.
+    pub seed: Option<u64>,
+    /// How many platforms are generated ahead of the slowest player.
+    pub lookahead: i32,
+    pub adaptive: bool,
+    pub private: bool,
+    pub skip_policy: SkipPolicy,
+    /// How fast the void advances in elimination rounds, in platforms per second.
+    pub void_rate: f64,
+    pub crumble: bool,
+    pub messages: Messages,
+    pub jump_table: HashMap<JumpKind, f64>,
+    pub palettes: Palettes,
+    pub courses: Vec<CourseConfig>,
+}
+
+impl Settings {
+    pub fn load(cli: &Cli) -> Result<Self, String> {
+        let file = match &cli.config {
+            Some(path) => read_config_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                read_config_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => ConfigFile::default(),
+        };
+
+        let world = cli
+            .path
+            .clone()
+            .or(file.world.path)
+            .ok_or("No world given, pass its path or set `world.path` in the config file.")?;
+
+        if !world.exists() {
+            return Err(format!("Directory `{}` does not exist.", world.display()));
+        }
+
+        if !world.is_dir() {
+            return Err(format!("`{}` is not a directory.", world.display()));
+        }
+
+        let spawn = match &cli.spawn {
+            Some(spawn) => parse_spawn(spawn)?,
+            None => file.world.spawn.unwrap_or(DEFAULT_SPAWN),
+        };
+
+        let preload_radius = cli
+            .preload_radius
+            .or(file.world.preload_radius)
+            .unwrap_or(16);
+        if !(1..=32).contains(&preload_radius) {
+            return Err(format!(
+                "The preload radius must be between 1 and 32 chunks, not {preload_radius}."
+            ));
+        }
+
+        let address = SocketAddr::new(
+            cli.address
+                .or(file.server.address)
+                .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            cli.port.or(file.server.port).unwrap_or(25565),
+        );
+
+        let max_players = cli
+            .max_players
+            .or(file.server.max_players)
+            .unwrap_or(20);
+        if max_players == 0 {
+            return Err("`max_players` must be at least 1.".to_owned());
+        }
+
+        let favicon = match cli.favicon.as_ref().or(file.server.favicon.as_ref()) {
+            Some(path) => read_favicon(path)?,
+            None => include_bytes!("logo.png").to_vec(),
+        };
+
+        let connection_mode = parse_connection_mode(
+            cli.connection_mode
+                .as_deref()
+                .or(file.server.connection_mode.as_deref())
+                .unwrap_or("online"),
+            file.server.prevent_proxy_connections.unwrap_or(false),
+            file.server.velocity_secret,
+        )?;
+
+        let rcon = if file.rcon.enabled {
+            match file.rcon.password {
+                Some(password) if !password.is_empty() => Some(RconSettings {
+                    port: file.rcon.port.unwrap_or(25575),
+                    password,
+                }),
+                _ => return Err("RCON needs `rcon.password` to be set.".to_owned()),
+            }
+        } else {
+            None
+        };
+
+        let http = file.http.enabled.then(|| HttpSettings {
+            port: file.http.port.unwrap_or(8080),
+            token: file.http.token.filter(|token| !token.is_empty()),
+        });
+
+        let difficulty = match cli.difficulty.as_ref().or(file.gameplay.difficulty.as_ref()) {
+            Some(name) => parse_difficulty(name)?,
+            None => Preset::default(),
+        };
+
+        let lookahead = cli
+            .lookahead
+            .or(file.gameplay.lookahead)
+            .unwrap_or(1500);
+        if lookahead < 1 {
+            return Err(format!("`lookahead` must be at least 1, not {lookahead}."));
+        }
+
+        let skip_policy = match cli.skip_policy.as_ref().or(file.gameplay.skip_policy.as_ref()) {
+            Some(name) => {
+                SkipPolicy::parse(name).map_err(|e| format!("Invalid config: {e}."))?
+            }
+            None => SkipPolicy::default(),
+        };
+
+        let void_rate = cli
+            .void_rate
+            .or(file.gameplay.void_rate)
+            .unwrap_or(0.5);
+        if !(void_rate > 0.0 && void_rate.is_finite()) {
+            return Err(format!("`void_rate` must be a positive number, not {void_rate}."));
+        }
+
+        let jump_table = file
+            .jumps
+            .iter()
+            .map(|(name, weight)| {
+                let kind = JumpKind::from_name(name)
+                    .ok_or_else(|| format!("Unknown jump kind `{name}` in the jump table."))?;
+
+                if !(*weight >= 0.0 && weight.is_finite()) {
+                    return Err(format!(
+                        "The weight of `{name}` jumps must be a finite, non-negative number."
+                    ));
+                }
+
+                Ok((kind, *weight))
+            })
+            .collect::<Result<HashMap<_, _>, String>>()?;
+
+        let palettes =
+            Palettes::parse(&file.palettes).map_err(|e| format!("Invalid config: {e}."))?;
+
+        // Courses from the command line replace the ones in the file
+        let mut courses = if cli.courses.is_empty() {
+            file.courses
+                .into_iter()
+                .map(|course| course.resolve(difficulty))
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            cli.courses
+                .iter()
+                .map(|spec| {
+                    CourseConfig::parse(spec, difficulty)
+                        .map_err(|e| format!("Invalid course `{spec}`: {e}."))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        if courses.is_empty() {
+            courses.push(CourseConfig {
+                preset: difficulty,
+                ..Default::default()
+            });
+        }
+
+        for (i, course) in courses.iter().enumerate() {
+            if courses[..i].iter().any(|other| other.name == course.name) {
+                return Err(format!("There are two courses called `{}`.", course.name));
+            }
+        }
+
+        Ok(Self {
+            world,
+            spawn: BlockPos::new(spawn[0], spawn[1], spawn[2]),
+            preload_radius,
+            address,
+            max_players,
+            queue_size: cli.queue_size.or(file.server.queue_size).unwrap_or(0),
+            whitelist: cli.whitelist.or(file.server.whitelist).unwrap_or(false),
+            motd: cli
+                .motd
+                .clone()
+                .or(file.server.motd)
+                .unwrap_or_else(|| "A tini parkour server".to_owned()),
+            favicon,
+            connection_mode,
+            rcon,
+            http,
+            seed: cli.seed.or(file.gameplay.seed),
+            lookahead,
+            adaptive: cli.adaptive.or(file.gameplay.adaptive).unwrap_or(false),
+            private: cli.private.or(file.gameplay.private).unwrap_or(false),
+            skip_policy,
+            void_rate,
+            crumble: cli.crumble.or(file.gameplay.crumble).unwrap_or(false),
+            messages: file.messages,
+            jump_table,
+            palettes,
+            courses,
+        })
+    }
+
+    /// Takes over the settings that can change while the server is running.
+    /// Returns the names of the ones that changed, but can't be applied
+    /// until the server restarts.
+    pub fn apply(&mut self, new: Settings) -> Vec<&'static str> {
+        let mut need_restart = Vec::new();
+
+        if new.world != self.world {
+            need_restart.push("world");
+        }
+        if new.spawn != self.spawn {
+            need_restart.push("spawn");
+        }
+        if new.preload_radius != self.preload_radius {
+            need_restart.push("preload_radius");
+        }
+        if new.seed != self.seed {
+            need_restart.push("seed");
+        }
+        if new.private != self.private {
+            need_restart.push("private");
+        }
+        if new.courses != self.courses {
+            need_restart.push("courses");
+        }
+        if new.address != self.address {
+            need_restart.push("address");
+        }
+        if new.max_players != self.max_players {
+            need_restart.push("max_players");
+        }
+        if new.queue_size != self.queue_size {
+            need_restart.push("queue_size");
+        }
+        if new.motd != self.motd {
+            need_restart.push("motd");
+        }
+        if new.favicon != self.favicon {
+            need_restart.push("favicon");
+        }
+        if !same_connection_mode(&new.connection_mode, &self.connection_mode) {
+            need_restart.push("connection_mode");
+        }
+        if new.rcon != self.rcon {
+            need_restart.push("rcon");
+        }
+        if new.http != self.http {
+            need_restart.push("http");
+        }
+
+        self.whitelist = new.whitelist;
+        self.lookahead = new.lookahead;
+        self.adaptive = new.adaptive;
+        self.skip_policy = new.skip_policy;
+        self.void_rate = new.void_rate;
+        self.crumble = new.crumble;
+        self.messages = new.messages;
+        self.jump_table = new.jump_table;
+        self.palettes = new.palettes;
+
+        need_restart
+    }
+
+    pub fn course(&self, name: &str) -> Option<&CourseConfig> {
+        self.courses.iter().find(|course| course.name == name)
+    }
+}
+
+impl CourseSection {
+    fn resolve(self, difficulty: Preset) -> Result<CourseConfig, String> {
+        if self.name.is_empty() || self.name.contains(|c: char| c.is_whitespace() || c == ',') {
+            return Err(format!("Invalid course name `{}`.", self.name));
+        }
+
+        if self.length.is_some_and(|length| length < 1) {
+            return Err(format!("Course `{}` must be at least 1 platform long.", self.name));
+        }
+
+        Ok(CourseConfig {
+            preset: match &self.difficulty {
+                Some(name) => parse_difficulty(name)?,
+                None => difficulty,
+            },
+            name: self.name,
+            length: self.length,
+            seed: self.seed,
+        })
+    }
+}
+
+fn read_config_file(path: &Path) -> Result<ConfigFile, String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read the config file `{}`: {e}", path.display()))?;
+
+    toml::from_str(&contents)
+        .map_err(|e| format!("Invalid config file `{}`: {e}", path.display()))
+}
+
+fn read_favicon(path: &Path) -> Result<Vec<u8>, String> {
+    let favicon = fs::read(path)
+        .map_err(|e| format!("Could not read the favicon `{}`: {e}", path.display()))?;
+
+    if !favicon.starts_with(PNG_SIGNATURE) {
+        return Err(format!("The favicon `{}` is not a PNG image.", path.display()));
+    }
+
+    Ok(favicon)
+}
+
+fn parse_spawn(spawn: &str) -> Result<[i32; 3], String> {
+    let coordinates = spawn
+        .split(',')
+        .map(|coordinate| coordinate.trim().parse().ok())
+        .collect::<Option<Vec<i32>>>();
+
+    match coordinates.as_deref() {
+        Some(&[x, y, z]) => Ok([x, y, z]),
+        _ => Err(format!("Invalid spawn `{spawn}`, expected `X,Y,Z`.")),
+    }
+}
+
+fn parse_difficulty(name: &str) -> Result<Preset, String> {
+    Preset::from_name(name)
+        .ok_or_else(|| format!("Unknown difficulty `{name}`, use easy, normal or hard."))
+}
+
+/// How players are authenticated: by Mojang, not at all, or by a proxy in
+/// front of the server forwarding their identity.
+fn parse_connection_mode(
+    name: &str,
+    prevent_proxy_connections: bool,
+    velocity_secret: Option<String>,
+) -> Result<ConnectionMode, String> {
+    match name {
+        "online" => Ok(ConnectionMode::Online {
+            prevent_proxy_connections,
+        }),
+        "offline" => Ok(ConnectionMode::Offline),
+        "bungeecord" => Ok(ConnectionMode::BungeeCord),
+        "velocity" => match velocity_secret {
+            Some(secret) if !secret.is_empty() => Ok(ConnectionMode::Velocity {
+                secret: Arc::from(secret),
+            }),
+            _ => Err("Velocity forwarding needs `server.velocity_secret` to be set.".to_owned()),
+        },
+        _ => Err(format!(
+            "Unknown connection mode `{name}`, use online, offline, bungeecord or velocity."
+        )),
+    }
+}
+
+/// Whether two connection modes are the same, as `ConnectionMode` can't be
+/// compared directly.
+fn same_connection_mode(a: &ConnectionMode, b: &ConnectionMode) -> bool {
+    match (a, b) {
+        (
+            ConnectionMode::Online {
+                prevent_proxy_connections: a,
+            },
+            ConnectionMode::Online {
+                prevent_proxy_connections: b,
+            },
+        ) => a == b,
+        (ConnectionMode::Offline, ConnectionMode::Offline)
+        | (ConnectionMode::BungeeCord, ConnectionMode::BungeeCord) => true,
+        (ConnectionMode::Velocity { secret: a }, ConnectionMode::Velocity { secret: b }) => a == b,
+        _ => false,
+    }
+}
+
+/// Watches the config file, so that it can be reloaded without a restart.
+#[derive(Resource)]
+pub struct ConfigWatcher {
+    cli: Cli,
+    modified: Option<SystemTime>,
+    ticks: u32,
+    /// The settings that changed but wait for a restart, as of the last
+    /// reload.
+    need_restart: Vec<&'static str>,
+}
+
+impl ConfigWatcher {
+    pub fn new(cli: Cli) -> Self {
+        let mut watcher = Self {
+            cli,
+            modified: None,
+            ticks: 0,
+            need_restart: Vec::new(),
+        };
+        watcher.modified = watcher.modified_at();
+
+        watcher
+    }
+
+    fn path(&self) -> PathBuf {
+        self.cli
+            .config
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH))
+    }
+
+    fn modified_at(&self) -> Option<SystemTime> {
+        fs::metadata(self.path())
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    /// Whether the file changed since the last call. Only actually looks
+    /// every few ticks.
+    pub fn changed(&mut self) -> bool {
+        self.ticks += 1;
+        if self.ticks < WATCH_INTERVAL {
+            return false;
+        }
+        self.ticks = 0;
+
+        let modified = self.modified_at();
+        if modified == self.modified {
+            return false;
+        }
+
+        self.modified = modified;
+        true
+    }
+
+    pub fn reload(&self) -> Result<Settings, String> {
+        Settings::load(&self.cli)
+    }
+
+    /// Remembers which settings wait for a restart, and returns the ones that
+    /// weren't reported on an earlier reload.
+    pub fn unreported(&mut self, need_restart: Vec<&'static str>) -> Vec<&'static str> {
+        let unreported = need_restart
+            .iter()
+            .filter(|name| !self.need_restart.contains(name))
+            .copied()
+            .collect();
+        self.need_restart = need_restart;
+
+        unreported
+    }
+}
@@ -0,0 +1,111 @@
+use std::io;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+
+use valence::command::scopes::CommandScopes;
+use valence::command::CommandExecutionEvent;
+use valence::prelude::*;
+
+use crate::permissions::{group_scope, OPERATOR_GROUP};
+use crate::rcon::RemoteExecutor;
+
+/// The reply when the console runs a command that needs a player.
+const PLAYERS_ONLY: &str = "Only players can use this command.";
+
+/// The server console, running commands typed on stdin like an operator.
+#[derive(Component)]
+pub struct Console;
+
+/// Lines read from stdin, on a separate thread so reading doesn't block.
+#[derive(Resource)]
+pub struct ConsoleInput(Mutex<Receiver<String>>);
+
+pub fn spawn_console(mut commands: Commands) {
+    let mut scopes = CommandScopes::default();
+    scopes.add(&group_scope(OPERATOR_GROUP));
+    commands.spawn((Console, scopes));
+
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    commands.insert_resource(ConsoleInput(Mutex::new(receiver)));
+}
+
+pub fn read_console(
+    input: Res<ConsoleInput>,
+    console: Query<Entity, With<Console>>,
+    mut executions: EventWriter<CommandExecutionEvent>,
+) {
+    let receiver = input.0.lock().unwrap();
+
+    while let Ok(line) = receiver.try_recv() {
+        // Commands can be typed like in chat, or without the slash
+        let command = line.trim().trim_start_matches('/');
+        if command.is_empty() {
+            continue;
+        }
+
+        executions.send(CommandExecutionEvent {
+            command: command.to_owned(),
+            executor: console.single(),
+        });
+    }
+}
+
+/// Sends the output of a command to the player or RCON client who ran it, or
+/// prints it if it was the console.
+pub fn reply(
+    clients: &mut Query<&mut Client>,
+    remotes: &Query<&RemoteExecutor>,
+    executor: Entity,
+    message: Text,
+) {
+    if let Ok(mut client) = clients.get_mut(executor) {
+        client.send_chat_message(message);
+    } else {
+        reply_to_console(remotes, executor, plain_text(&message));
+    }
+}
+
+/// Tells the console or RCON client that ran a command that only players can
+/// use it.
+pub fn players_only(remotes: &Query<&RemoteExecutor>, executor: Entity) {
+    reply_to_console(remotes, executor, PLAYERS_ONLY.to_owned());
+}
+
+fn reply_to_console(remotes: &Query<&RemoteExecutor>, executor: Entity, line: String) {
+    if let Ok(remote) = remotes.get(executor) {
+        remote.send(line);
+    } else {
+        println!("{line}");
+    }
+}
+
+/// The text without any formatting codes, for printing it.
+pub fn plain_text(text: &Text) -> String {
+    let legacy = text.to_legacy_lossy();
+    let mut chars = legacy.chars();
+    let mut plain = String::new();
+
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            // Skip the formatting code after it
+            chars.next();
+        } else {
+            plain.push(c);
+        }
+    }
+
+    plain
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+
+use crate::{jump::JumpKind, stats::JumpStats};
+
+/// The fail rate we try to keep players at: low enough to not be frustrating,
+/// high enough to not be boring.
+const TARGET_FAIL_RATE: f64 = 0.25;
+
+/// How many attempts of a kind we need before we trust its fail rate.
+const MIN_ATTEMPTS: u32 = 5;
+
+/// How far the share of harder jumps has to move from the baseline before the
+/// course counts as a level harder or easier.
+const LEVEL_MARGIN: f64 = 0.1;
+
+/// The baseline a course starts from, before adapting to its players.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Preset {
+    Easy,
+    #[default]
+    Normal,
+    Hard,
+}
+
+impl Preset {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Easy => "easy",
+            Preset::Normal => "normal",
+            Preset::Hard => "hard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "easy" => Some(Preset::Easy),
+            "normal" => Some(Preset::Normal),
+            "hard" => Some(Preset::Hard),
+            _ => None,
+        }
+    }
+
+    fn base_weight(&self, kind: JumpKind) -> f64 {
+        match (self, kind) {
+            (Preset::Easy, JumpKind::Up) => 0.5,
+            (Preset::Hard, _) if kind.easier().is_some() => 0.5,
+            (_, _) if kind.easier().is_some() => 0.0,
+            (_, _) => 1.0,
+        }
+    }
+}
+
+/// Weights of the jump kinds the generator picks from. A kind with weight zero
+/// is never placed; the others are tried in a weighted random order.
+#[derive(Debug, Clone)]
+pub struct Difficulty {
+    preset: Preset,
+    /// Base weights overriding the preset's.
+    jump_table: HashMap<JumpKind, f64>,
+    weights: HashMap<JumpKind, f64>,
+}
+
+impl Difficulty {
+    pub fn new(preset: Preset) -> Self {
+        let mut difficulty = Self {
+            preset,
+            jump_table: HashMap::new(),
+            weights: HashMap::new(),
+        };
+        difficulty.reset_weights();
+
+        difficulty
+    }
+
+    pub fn set_jump_table(&mut self, jump_table: HashMap<JumpKind, f64>) {
+        self.jump_table = jump_table;
+        self.reset_weights();
+    }
+
+    fn reset_weights(&mut self) {
+        self.weights = JumpKind::TEMPLATES
+            .iter()
+            .map(|kind| (*kind, self.base_weight(*kind)))
+            .collect();
+    }
+
+    fn base_weight(&self, kind: JumpKind) -> f64 {
+        let weight = self.jump_table.get(&kind).copied();
+        weight.unwrap_or_else(|| self.preset.base_weight(kind))
+    }
+
+    pub fn preset(&self) -> Preset {
+        self.preset
+    }
+
+    pub fn weight(&self, kind: JumpKind) -> f64 {
+        self.weights.get(&kind).copied().unwrap_or(0.0)
+    }
+
+    pub fn kinds(&self) -> Vec<JumpKind> {
+        JumpKind::TEMPLATES
+            .iter()
+            .filter(|kind| self.weight(**kind) > 0.0)
+            .copied()
+            .collect()
+    }
+
+    /// How hard the course is right now, from 0 to 3: the preset, one step up
+    /// when harder jumps became more common than the baseline and one step
+    /// down when they became rarer.
+    pub fn level(&self) -> usize {
+        let harder_share = |weight: &dyn Fn(JumpKind) -> f64| {
+            let total: f64 = JumpKind::TEMPLATES.iter().map(|kind| weight(*kind)).sum();
+            let harder: f64 = JumpKind::TEMPLATES
+                .iter()
+                .filter(|kind| kind.easier().is_some())
+                .map(|kind| weight(*kind))
+                .sum();
+
+            if total > 0.0 {
+                harder / total
+            } else {
+                0.0
+            }
+        };
+
+        let preset = match self.preset {
+            Preset::Easy => 0,
+            Preset::Normal => 1,
+            Preset::Hard => 2,
+        };
+        let base = harder_share(&|kind| self.base_weight(kind));
+        let adapted = harder_share(&|kind| self.weight(kind));
+
+        if adapted > base + LEVEL_MARGIN {
+            preset + 1
+        } else if adapted < base - LEVEL_MARGIN {
+            preset.saturating_sub(1)
+        } else {
+            preset
+        }
+    }
+
+    /// Recomputes the weights from how a player has been doing on each kind of jump.
+    pub fn adapt(&mut self, stats: &HashMap<JumpKind, JumpStats>) {
+        let fail_rate = |kind: JumpKind| {
+            stats
+                .get(&kind)
+                .filter(|stats| stats.attempts >= MIN_ATTEMPTS)
+                .and_then(|stats| stats.fail_rate())
+        };
+
+        self.reset_weights();
+
+        for kind in JumpKind::TEMPLATES {
+            let mut weight = self.weight(kind);
+
+            // Harder variants become more common once their easier kind is
+            // cleared comfortably below the target fail rate.
+            if let Some(easier) = kind.easier() {
+                if let Some(rate) = fail_rate(easier) {
+                    weight = (weight + (TARGET_FAIL_RATE - rate) * 4.0).clamp(0.0, 1.0);
+                }
+            }
+
+            // Kinds failed more often than the target get rarer, kinds failed
+            // less often get more common.
+            if let Some(rate) = fail_rate(kind) {
+                weight *= (1.0 + (TARGET_FAIL_RATE - rate) * 4.0).clamp(0.1, 2.0);
+            }
+
+            self.weights.insert(kind, weight);
+        }
+    }
+}
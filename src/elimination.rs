@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use valence::prelude::*;
+
+use crate::parkour::PlayerOnCourse;
+
+const TICKS_PER_SECOND: f64 = 20.0;
+
+/// A last-one-standing round. A kill plane starts at the first platform and
+/// moves along the course, eliminating everyone it catches up with.
+pub struct Elimination {
+    remaining: Vec<(Uuid, String)>,
+    eliminated: Vec<Uuid>,
+    front: f64,
+    /// Platforms per second.
+    rate: f64,
+    crumble: bool,
+}
+
+impl Elimination {
+    pub fn new(players: Vec<(Uuid, String)>, rate: f64, crumble: bool) -> Self {
+        Self {
+            remaining: players,
+            eliminated: Vec::new(),
+            front: 0.0,
+            rate,
+            crumble,
+        }
+    }
+
+    /// The platform the kill plane is at.
+    pub fn front(&self) -> i32 {
+        self.front as i32
+    }
+
+    /// Moves the kill plane forward by a tick, but never past the last
+    /// platform, so that finishing the course is safe.
+    pub fn advance(&mut self, last_platform: i32) {
+        self.front = (self.front + self.rate / TICKS_PER_SECOND).min(last_platform as f64);
+    }
+
+    /// Takes the players the kill plane caught up with out of the round.
+    pub fn eliminate(&mut self, states: &HashMap<Uuid, PlayerOnCourse>) -> Vec<(Uuid, String)> {
+        let front = self.front();
+
+        let (caught, remaining) = self.remaining.drain(..).partition(|(player_id, _)| {
+            states
+                .get(player_id)
+                .map(|state| state.current_platform() < front)
+                .unwrap_or(true)
+        });
+        self.remaining = remaining;
+
+        let caught: Vec<(Uuid, String)> = caught;
+        self.eliminated
+            .extend(caught.iter().map(|(player_id, _)| *player_id));
+
+        caught
+    }
+
+    pub fn is_eliminated(&self, player_id: &Uuid) -> bool {
+        self.eliminated.contains(player_id)
+    }
+
+    /// Whether the platforms behind the kill plane crumble away.
+    pub fn crumbles(&self) -> bool {
+        self.crumble
+    }
+
+    /// The round is over once at most one player is left, or everyone left
+    /// has made it to the end.
+    pub fn over(&self, states: &HashMap<Uuid, PlayerOnCourse>) -> bool {
+        self.remaining.len() <= 1
+            || self.remaining.iter().all(|(player_id, _)| {
+                states
+                    .get(player_id)
+                    .map(|state| state.finished())
+                    .unwrap_or(true)
+            })
+    }
+
+    pub fn results(&self) -> Text {
+        match self.remaining.as_slice() {
+            [] => "Everyone was eliminated!".color(Color::YELLOW).bold(),
+            [(_, winner)] => winner.clone().color(Color::AQUA).bold()
+                + " is the last one standing!".color(Color::YELLOW).bold(),
+            survivors => {
+                let names = survivors
+                    .iter()
+                    .map(|(_, username)| username.as_str())
+                    .collect::<Vec<_>>();
+
+                "Survivors: ".color(Color::YELLOW).bold()
+                    + names.join(", ").color(Color::AQUA).not_bold()
+            }
+        }
+    }
+}
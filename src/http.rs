@@ -0,0 +1,402 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use valence::prelude::*;
+
+use crate::config::Settings;
+use crate::rcon::{run_remote_command, RemoteCommand, RemoteCommands};
+use crate::status::ServerStatus;
+
+/// Longer requests are cut off, and rejected as malformed.
+const MAX_REQUEST_LENGTH: u64 = 16 * 1024;
+
+/// Clients that don't send their request in time are hung up on.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the request handlers share.
+struct Api {
+    status: ServerStatus,
+    /// Needed to make changes. They are disabled without one.
+    token: Option<String>,
+    commands: Sender<RemoteCommand>,
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Option<String>,
+    token: Option<String>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// The value of a parameter in the query string. Course names need no
+    /// escaping, so values aren't decoded.
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .as_deref()?
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct ResetRequest {
+    /// Every course if not set.
+    course: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SeedRequest {
+    course: String,
+    seed: u64,
+}
+
+/// Starts serving the JSON API on the local port, if enabled.
+pub fn start_http(
+    settings: Res<Settings>,
+    status: Res<ServerStatus>,
+    remote_commands: Res<RemoteCommands>,
+) {
+    let Some(http) = &settings.http else {
+        return;
+    };
+
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, http.port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("failed to start the HTTP API on port {}: {e}", http.port);
+            return;
+        }
+    };
+
+    let api = Arc::new(Api {
+        status: status.clone(),
+        token: http.token.clone(),
+        commands: remote_commands.sender(),
+    });
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+
+            let api = api.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(&stream, &api) {
+                    eprintln!("HTTP request failed: {e}");
+                }
+            });
+        }
+    });
+
+    println!("HTTP API listening on port {}", http.port);
+}
+
+fn handle_connection(stream: &TcpStream, api: &Api) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+
+    let (status, body) = match read_request(stream) {
+        Ok(request) => route(api, &request),
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(()),
+        Err(e) => error(400, &e.to_string()),
+    };
+
+    write_response(stream, status, &body)
+}
+
+fn route(api: &Api, request: &Request) -> (u16, String) {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/status") => ok(&api.status.instances()),
+        ("GET", "/players") => ok(&api.status.players()),
+        ("GET", "/leaderboard") => leaderboard(api, request),
+        ("GET", "/stats") => ok(&api.status.generation()),
+        ("POST", "/reset" | "/seed") if api.token.is_none() => {
+            error(403, "Changes are disabled, set `http.token` to enable them.")
+        }
+        ("POST", "/reset" | "/seed") if request.token != api.token => {
+            error(401, "Missing or wrong token.")
+        }
+        ("POST", "/reset") => reset(api, &request.body),
+        ("POST", "/seed") => reseed(api, &request.body),
+        (_, "/status" | "/players" | "/leaderboard" | "/stats" | "/reset" | "/seed") => {
+            error(405, "Method not allowed.")
+        }
+        _ => error(404, "Not found."),
+    }
+}
+
+fn leaderboard(api: &Api, request: &Request) -> (u16, String) {
+    match request.param("course") {
+        Some(course) if !course_exists(api, course) => {
+            error(404, &format!("There is no course called {course}."))
+        }
+        course => ok(&api.status.leaderboard(course)),
+    }
+}
+
+fn reset(api: &Api, body: &[u8]) -> (u16, String) {
+    let request = if body.is_empty() {
+        ResetRequest::default()
+    } else {
+        match serde_json::from_slice::<ResetRequest>(body) {
+            Ok(request) => request,
+            Err(e) => return error(400, &format!("Invalid request: {e}.")),
+        }
+    };
+
+    let command = match request.course {
+        Some(course) if !course_exists(api, &course) => {
+            return error(404, &format!("There is no course called {course}."));
+        }
+        Some(course) => format!("regenerate {course}"),
+        None => "regenerate".to_owned(),
+    };
+
+    run(api, command)
+}
+
+fn reseed(api: &Api, body: &[u8]) -> (u16, String) {
+    let request = match serde_json::from_slice::<SeedRequest>(body) {
+        Ok(request) => request,
+        Err(e) => return error(400, &format!("Invalid request: {e}.")),
+    };
+
+    if !course_exists(api, &request.course) {
+        return error(404, &format!("There is no course called {}.", request.course));
+    }
+
+    run(api, format!("reseed {} {}", request.course, request.seed))
+}
+
+/// Course names never contain spaces, so a known one is safe to put in a
+/// command.
+fn course_exists(api: &Api, course: &str) -> bool {
+    api.status
+        .instances()
+        .iter()
+        .any(|instance| instance.name == course)
+}
+
+fn run(api: &Api, command: String) -> (u16, String) {
+    match run_remote_command(&api.commands, command) {
+        Some(message) => ok(&json!({ "message": message })),
+        None => error(503, "The server is shutting down."),
+    }
+}
+
+fn ok(body: &impl Serialize) -> (u16, String) {
+    match serde_json::to_string(body) {
+        Ok(json) => (200, json),
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+fn error(status: u16, message: &str) -> (u16, String) {
+    (status, json!({ "error": message }).to_string())
+}
+
+fn read_request(stream: impl Read) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message);
+    let mut reader = BufReader::new(stream.take(MAX_REQUEST_LENGTH));
+
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Err(ErrorKind::UnexpectedEof.into());
+    }
+
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(invalid("Malformed request line."));
+    };
+    let method = method.to_owned();
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_owned(), Some(query.to_owned())),
+        None => (target.to_owned(), None),
+    };
+
+    let mut content_length = 0;
+    let mut token = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("Incomplete headers."));
+        }
+
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+
+        let Some((name, value)) = header.split_once(':') else {
+            return Err(invalid("Malformed header."));
+        };
+        let value = value.trim();
+
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = value
+                .parse()
+                .map_err(|_| invalid("Invalid Content-Length."))?;
+        } else if name.eq_ignore_ascii_case("authorization") {
+            token = value.strip_prefix("Bearer ").map(str::to_owned);
+        }
+    }
+
+    if content_length as u64 > MAX_REQUEST_LENGTH {
+        return Err(invalid("Body too long."));
+    }
+
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|_| invalid("Incomplete body."))?;
+
+    Ok(Request {
+        method,
+        path,
+        query,
+        token,
+        body,
+    })
+}
+
+fn write_response(mut stream: &TcpStream, status: u16, body: &str) -> io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status} {reason}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {body}",
+        body.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    fn api(token: Option<&str>) -> Api {
+        // Nothing runs the commands, as if the server was shutting down
+        let (commands, _) = mpsc::channel();
+
+        Api {
+            status: ServerStatus::default(),
+            token: token.map(str::to_owned),
+            commands,
+        }
+    }
+
+    fn request(method: &str, target: &str, token: Option<&str>) -> Request {
+        let mut raw = format!("{method} {target} HTTP/1.1\r\nHost: localhost\r\n");
+        if let Some(token) = token {
+            raw += &format!("Authorization: Bearer {token}\r\n");
+        }
+        raw += "\r\n";
+
+        read_request(raw.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn reads_request() {
+        let raw = b"POST /seed?x=1 HTTP/1.1\r\n\
+            Authorization: Bearer secret\r\n\
+            Content-Length: 4\r\n\
+            \r\n\
+            body";
+        let request = read_request(&raw[..]).unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/seed");
+        assert_eq!(request.query.as_deref(), Some("x=1"));
+        assert_eq!(request.token.as_deref(), Some("secret"));
+        assert_eq!(request.body, b"body");
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        let incomplete = b"GET /status HTTP/1.1\r\nHost: localhost\r\n";
+        let bad_length = b"POST /seed HTTP/1.1\r\nContent-Length: x\r\n\r\n";
+        let short_body = b"POST /seed HTTP/1.1\r\nContent-Length: 10\r\n\r\nbody";
+        let long_body = b"POST /seed HTTP/1.1\r\nContent-Length: 100000\r\n\r\n";
+
+        for raw in [&incomplete[..], bad_length, short_body, long_body] {
+            let e = read_request(raw).err().unwrap();
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+        }
+
+        let e = read_request(&b""[..]).err().unwrap();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn reads_query_parameters() {
+        let with_query = request("GET", "/leaderboard?a=1&course=main", None);
+        let without_query = request("GET", "/leaderboard", None);
+
+        assert_eq!(with_query.param("course"), Some("main"));
+        assert_eq!(with_query.param("a"), Some("1"));
+        assert_eq!(with_query.param("b"), None);
+        assert_eq!(without_query.param("course"), None);
+    }
+
+    #[test]
+    fn routes_requests() {
+        let api = api(Some("secret"));
+        let status = |method, target| route(&api, &request(method, target, None)).0;
+
+        assert_eq!(status("GET", "/status"), 200);
+        assert_eq!(status("GET", "/players"), 200);
+        assert_eq!(status("GET", "/leaderboard"), 200);
+        assert_eq!(status("GET", "/stats"), 200);
+        assert_eq!(status("GET", "/leaderboard?course=nowhere"), 404);
+        assert_eq!(status("GET", "/unknown"), 404);
+        assert_eq!(status("DELETE", "/status"), 405);
+        assert_eq!(status("GET", "/reset"), 405);
+    }
+
+    #[test]
+    fn checks_the_token() {
+        let api = api(Some("secret"));
+        let status = |token| route(&api, &request("POST", "/reset", token)).0;
+
+        assert_eq!(status(None), 401);
+        assert_eq!(status(Some("wrong")), 401);
+        // Let through, but nothing runs the command
+        assert_eq!(status(Some("secret")), 503);
+    }
+
+    #[test]
+    fn changes_need_a_token_configured() {
+        let api = api(None);
+
+        assert_eq!(route(&api, &request("POST", "/reset", None)).0, 403);
+        assert_eq!(route(&api, &request("POST", "/seed", Some("secret"))).0, 403);
+    }
+}
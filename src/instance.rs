@@ -0,0 +1,81 @@
+use valence::prelude::*;
+
+use crate::difficulty::Preset;
+
+/// How a course instance is set up. Given on the command line as
+/// `NAME[:DIFFICULTY[:LENGTH[:SEED]]]`, where `LENGTH` is a number of
+/// platforms or `endless`, or as a `[[courses]]` table in the config file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CourseConfig {
+    pub name: String,
+    pub preset: Preset,
+    pub length: Option<i32>,
+    pub seed: Option<u64>,
+}
+
+impl Default for CourseConfig {
+    fn default() -> Self {
+        Self {
+            name: "main".to_owned(),
+            preset: Preset::default(),
+            length: None,
+            seed: None,
+        }
+    }
+}
+
+impl CourseConfig {
+    /// Parses a course given on the command line. Courses that don't set a
+    /// difficulty get the default one.
+    pub fn parse(spec: &str, default_preset: Preset) -> Result<Self, String> {
+        let mut parts = spec.split(':');
+
+        let name = parts.next().unwrap_or_default().trim();
+        // Runs are stored with their course name, in a comma separated file
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ',') {
+            return Err(format!("invalid course name `{name}`"));
+        }
+
+        let preset = match parts.next() {
+            Some(preset) => Preset::from_name(preset)
+                .ok_or_else(|| format!("unknown difficulty `{preset}`, use easy, normal or hard"))?,
+            None => default_preset,
+        };
+
+        let length = match parts.next() {
+            Some("endless") | None => None,
+            Some(length) => Some(
+                length
+                    .parse()
+                    .ok()
+                    .filter(|length| *length > 0)
+                    .ok_or_else(|| format!("invalid course length `{length}`"))?,
+            ),
+        };
+
+        let seed = match parts.next() {
+            Some(seed) => Some(
+                seed.parse()
+                    .map_err(|_| format!("invalid course seed `{seed}`"))?,
+            ),
+            None => None,
+        };
+
+        if parts.next().is_some() {
+            return Err(format!("too many parts in course `{spec}`"));
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            preset,
+            length,
+            seed,
+        })
+    }
+}
+
+/// Which configured course a course layer is an instance of.
+#[derive(Component)]
+pub struct CourseInstance {
+    pub name: String,
+}
@@ -1,6 +1,73 @@
 use valence::{BlockPos, BlockState, ChunkLayer};
 
-use crate::{block_chooser::choose_block, port::Port};
+use crate::{
+    block_chooser::{choose_block, Palettes},
+    port::Port,
+    stats::JumpStats,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JumpKind {
+    Start,
+    Flat,
+    LongFlat,
+    Up,
+    LongUp,
+    Down,
+    LongDown,
+}
+
+impl JumpKind {
+    pub const TEMPLATES: [JumpKind; 6] = [
+        JumpKind::Flat,
+        JumpKind::LongFlat,
+        JumpKind::Up,
+        JumpKind::LongUp,
+        JumpKind::Down,
+        JumpKind::LongDown,
+    ];
+
+    /// How far ahead and how far up the next platform is placed.
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            JumpKind::Start => (0, 0),
+            JumpKind::Flat => (5, 0),
+            JumpKind::LongFlat => (6, 0),
+            JumpKind::Up => (4, 1),
+            JumpKind::LongUp => (5, 1),
+            JumpKind::Down => (4, -1),
+            JumpKind::LongDown => (6, -1),
+        }
+    }
+
+    pub fn easier(&self) -> Option<JumpKind> {
+        match self {
+            JumpKind::LongFlat => Some(JumpKind::Flat),
+            JumpKind::LongUp => Some(JumpKind::Up),
+            JumpKind::LongDown => Some(JumpKind::Down),
+            _ => None,
+        }
+    }
+
+    /// The kind a jump table entry is for, e.g. `long_flat`.
+    pub fn from_name(name: &str) -> Option<JumpKind> {
+        JumpKind::TEMPLATES
+            .into_iter()
+            .find(|kind| kind.name() == name.replace('_', " "))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            JumpKind::Start => "start",
+            JumpKind::Flat => "flat",
+            JumpKind::LongFlat => "long flat",
+            JumpKind::Up => "up",
+            JumpKind::LongUp => "long up",
+            JumpKind::Down => "down",
+            JumpKind::LongDown => "long down",
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct JumpBlock {
@@ -11,6 +78,8 @@ pub struct JumpBlock {
 #[derive(Clone)]
 pub struct Jump {
     pub endpoint: Port,
+    pub kind: JumpKind,
+    pub stats: JumpStats,
     blocks: Vec<JumpBlock>,
 }
 
@@ -146,9 +215,9 @@ impl Jump {
     }
 }
 
-pub fn two_by_two_to_block_pos(pos: BlockPos, layer: &ChunkLayer) -> Jump {
+pub fn two_by_two_to_block_pos(pos: BlockPos, layer: &ChunkLayer, palettes: &Palettes) -> Jump {
     let biome = layer.biome(pos);
-    let state = choose_block(biome);
+    let state = choose_block(biome, palettes);
 
     let mut blocks = Vec::new();
 
@@ -164,6 +233,8 @@ pub fn two_by_two_to_block_pos(pos: BlockPos, layer: &ChunkLayer) -> Jump {
     let end = Port::TwoByTwo(pos);
     Jump {
         endpoint: end,
+        kind: JumpKind::Start,
+        stats: JumpStats::default(),
         blocks,
     }
 }
@@ -0,0 +1,79 @@
+use valence::math::DVec3;
+use valence::prelude::*;
+
+/// Half the width of the spawn platform, in blocks.
+const PLATFORM_RADIUS: i32 = 4;
+
+const PLATFORM_Y: i32 = 64;
+
+/// Marks the void layer players wait in before they pick a course.
+#[derive(Component)]
+pub struct Lobby;
+
+/// Marks the void layer players wait in for a free slot when the server is
+/// full.
+#[derive(Component)]
+pub struct WaitingRoom;
+
+/// A player waiting in the lobby for the course they joined to be ready.
+#[derive(Component)]
+pub struct PendingJoin(pub Entity);
+
+pub fn lobby_spawn() -> DVec3 {
+    DVec3::new(0.5, PLATFORM_Y as f64 + 1.0, 0.5)
+}
+
+pub fn spawn_lobby(
+    commands: &mut Commands,
+    dimensions: &DimensionTypeRegistry,
+    biomes: &BiomeRegistry,
+    server: &Server,
+) -> Entity {
+    let layer = platform_layer(dimensions, biomes, server, BlockState::PURPLE_CONCRETE);
+
+    commands.spawn((layer, Lobby)).id()
+}
+
+pub fn spawn_waiting_room(
+    commands: &mut Commands,
+    dimensions: &DimensionTypeRegistry,
+    biomes: &BiomeRegistry,
+    server: &Server,
+) -> Entity {
+    let layer = platform_layer(dimensions, biomes, server, BlockState::GRAY_CONCRETE);
+
+    commands.spawn((layer, WaitingRoom)).id()
+}
+
+/// A void layer with just a platform at the spawn, with an edge of `edge`.
+fn platform_layer(
+    dimensions: &DimensionTypeRegistry,
+    biomes: &BiomeRegistry,
+    server: &Server,
+    edge: BlockState,
+) -> LayerBundle {
+    let mut layer = LayerBundle::new(ident!("overworld"), dimensions, biomes, server);
+
+    for z in -2..2 {
+        for x in -2..2 {
+            layer.chunk.insert_chunk([x, z], UnloadedChunk::new());
+        }
+    }
+
+    for z in -PLATFORM_RADIUS..=PLATFORM_RADIUS {
+        for x in -PLATFORM_RADIUS..=PLATFORM_RADIUS {
+            let on_edge = x.abs() == PLATFORM_RADIUS || z.abs() == PLATFORM_RADIUS;
+
+            layer.chunk.set_block(
+                [x, PLATFORM_Y, z],
+                if on_edge {
+                    edge
+                } else {
+                    BlockState::SMOOTH_QUARTZ
+                },
+            );
+        }
+    }
+
+    layer
+}
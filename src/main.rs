@@ -1,44 +1,209 @@
+mod access;
 mod block_chooser;
+mod config;
+mod console;
+mod difficulty;
+mod elimination;
+mod http;
+mod instance;
 mod jump;
+mod lobby;
 mod parkour;
+mod permissions;
 mod port;
+mod practice;
+mod progress_bar;
+mod queue;
+mod race;
+mod rcon;
+mod records;
+mod relay;
+mod sidebar;
+mod spectate;
+mod stats;
+mod status;
+mod team;
+mod terrain;
 
-use std::collections::HashMap;
-use std::net::SocketAddr;
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::time::Duration;
 
-use valence::network::{async_trait, HandshakeData, ServerListPing};
+use valence::network::{
+    async_trait, HandshakeData, NewClientInfo, PlayerSampleEntry, ServerListPing,
+};
 use valence::prelude::*;
 use valence::{MINECRAFT_VERSION, PROTOCOL_VERSION};
 
+use access::{disconnect_kicked_players, kick, AccessLists};
 use clap::Parser;
-use parkour::{ParkourCourse, PlayerOnCourse, PlayerStateUpdate};
+use config::{ConfigWatcher, Settings};
+use console::{players_only, read_console, reply, spawn_console};
+use elimination::Elimination;
+use http::start_http;
+use instance::{CourseConfig, CourseInstance};
+use jump::JumpKind;
+use lobby::{lobby_spawn, spawn_lobby, spawn_waiting_room, Lobby, PendingJoin, WaitingRoom};
+use parkour::{ParkourCourse, PlayerOnCourse, PlayerStateUpdate, SkipPolicy};
+use permissions::{group_scope, Permissions, OPERATOR_GROUP};
+use practice::{ask_server_for_platforms, platform_suggestions};
+use progress_bar::{difficulty_color, ProgressBar};
+use queue::JoinQueue;
+use race::{Race, RaceTick};
+use rcon::{run_remote_commands, start_rcon, RemoteCommands, RemoteExecutor};
+use records::{format_duration, RunRecord, Records};
+use relay::Relay;
+use sidebar::Sidebar;
+use spectate::{set_camera, Spectating};
+use stats::JumpStats;
+use status::{GenerationStatus, InstanceStatus, PlayerStatus, RunStatus, ServerStatus};
+use team::Teams;
+use terrain::TerrainCache;
 use valence::anvil::{AnvilLevel, ChunkLoadEvent, ChunkLoadStatus};
 use valence::command::handler::CommandResultEvent;
+use valence::command::parsers::GreedyString;
 use valence::command::scopes::CommandScopes;
 use valence::command::{AddCommand, CommandScopeRegistry};
 use valence::command_macros::Command;
+use valence::entity::EntityId;
+use valence::event_loop::PacketEvent;
+use valence::math::DVec3;
 use valence::message::SendMessage;
 use valence::player_list::DisplayName;
+use valence::protocol::packets::play::command_suggestions_s2c::CommandSuggestionsMatch;
+use valence::protocol::packets::play::{CommandSuggestionsS2c, RequestCommandCompletionsC2s};
 use valence::protocol::sound::SoundCategory;
-use valence::protocol::Sound;
+use valence::protocol::{Sound, VarInt, WritePacket};
+use valence::title::SetTitle;
+use valence::Despawned;
 
-#[derive(Parser, Resource)]
+/// Command line flags. Everything can also be set in the config file, and
+/// flags given here take precedence over it.
+#[derive(Parser)]
 #[clap(author, version, about)]
 struct Cli {
     /// The path to a Minecraft world save containing a `region` subdirectory.
-    path: PathBuf,
-    /// The spawn position of the player.
-    #[clap(default_value = "0,196,0")]
-    spawn: String,
+    path: Option<PathBuf>,
+    /// The spawn position of the player, as `X,Y,Z`. Defaults to `0,196,0`.
+    spawn: Option<String>,
+    /// The config file to read. Defaults to `parkour.toml`, if it exists.
+    #[clap(long)]
+    config: Option<PathBuf>,
+    /// The address to listen on.
+    #[clap(long)]
+    address: Option<IpAddr>,
+    /// The port to listen on.
+    #[clap(long)]
+    port: Option<u16>,
+    /// How many players can be online at once.
+    #[clap(long)]
+    max_players: Option<usize>,
+    /// How many players can wait in a queue once the server is full.
+    #[clap(long)]
+    queue_size: Option<usize>,
+    /// Only let in players on the whitelist. `--whitelist false` turns it off.
+    #[clap(long, num_args = 0..=1, default_missing_value = "true")]
+    whitelist: Option<bool>,
+    /// How players are authenticated: online, offline, or forwarded by a
+    /// bungeecord or velocity proxy.
+    #[clap(long)]
+    connection_mode: Option<String>,
+    /// The first line of the server list description.
+    #[clap(long)]
+    motd: Option<String>,
+    /// A 64x64 PNG shown in the server list.
+    #[clap(long)]
+    favicon: Option<PathBuf>,
+    /// How many chunks around the spawn are loaded up front.
+    #[clap(long)]
+    preload_radius: Option<i32>,
+    /// The difficulty of courses that don't set their own: easy, normal or hard.
+    #[clap(long)]
+    difficulty: Option<String>,
+    /// How many platforms are generated ahead of the slowest player.
+    #[clap(long)]
+    lookahead: Option<i32>,
+    /// Adapt the generated jumps to how often the players fail them.
+    #[clap(long, num_args = 0..=1, default_missing_value = "true")]
+    adaptive: Option<bool>,
+    /// What happens when a player skips platforms: allow, warn or refuse.
+    #[clap(long)]
+    skip_policy: Option<String>,
+    /// Give every player their own copy of a course instead of sharing one.
+    #[clap(long, num_args = 0..=1, default_missing_value = "true")]
+    private: Option<bool>,
+    /// The seed of courses that don't set their own. Random if not set.
+    #[clap(long)]
+    seed: Option<u64>,
+    /// A course players can join, as `NAME[:DIFFICULTY[:LENGTH[:SEED]]]`.
+    /// May be given multiple times, replacing the courses in the config file.
+    #[clap(long = "course")]
+    courses: Vec<String>,
+    /// How fast the void advances in elimination rounds, in platforms per second.
+    #[clap(long)]
+    void_rate: Option<f64>,
+    /// Let the platforms behind the void crumble away in elimination rounds.
+    #[clap(long, num_args = 0..=1, default_missing_value = "true")]
+    crumble: Option<bool>,
 }
 
+/// The fail heatmap of a course is written to a file starting with this,
+/// followed by the course name and seed, before it is reset.
+const HEATMAP_PREFIX: &str = "jumpstats";
+
+/// Where finished runs are appended.
+const RECORDS_PATH: &str = "records.csv";
+
+/// Where permission groups and the players in them are set.
+const PERMISSIONS_PATH: &str = "permissions.toml";
+
+/// How many platforms a course needs before players are let onto it.
+const READY_PLATFORMS: i32 = 16;
+
+/// How many of the players furthest along are listed in the server list.
+const PLAYER_SAMPLE_SIZE: usize = 10;
+
+/// Players with this scope are told about things that need an admin's attention.
+const ADMIN_SCOPE: &str = "parkour.admin";
+
+/// Needed for `/jumpstats export` on top of the scope of `/jumpstats`.
+const JUMPSTATS_EXPORT_SCOPE: &str = "parkour.admin.jumpstats";
+
+/// How many teams `/relay top` lists.
+const RELAY_LEADERBOARD_SIZE: usize = 10;
+
+/// How many of the best runs the HTTP API lists.
+const LEADERBOARD_SIZE: usize = 100;
+
 #[derive(Component)]
 struct GameState {
+    /// The name of the course this is an instance of.
+    name: String,
     course: ParkourCourse,
     player_states: HashMap<Uuid, PlayerOnCourse>,
+    race: Option<Race>,
+    elimination: Option<Elimination>,
+    relays: Vec<Relay>,
+    teams: Teams,
+    adaptive: bool,
+    /// Whether a jump result was recorded, or a player left, since the
+    /// difficulty was last adapted.
+    stats_changed: bool,
+    skip_policy: SkipPolicy,
 }
 
+/// Marks a private copy of a course and the player it was made for, who can
+/// invite others onto it. It is despawned once everyone left.
+#[derive(Component)]
+struct CourseOwner(Entity);
+
+/// Lets a player join someone else's private copy of a course instead of
+/// getting their own.
+#[derive(Component)]
+struct Invitation(Entity);
+
 impl GameState {
     fn update_player_state(
         &mut self,
@@ -51,7 +216,101 @@ impl GameState {
             .entry(player_id)
             .or_insert_with(|| PlayerOnCourse::new(self.course.get_start().into()));
 
-        player_state.update_player_state(&self.course, pos, look)
+        let before = player_state.current_platform();
+        let update = player_state.update_player_state(&self.course, pos, look, self.skip_policy);
+        let after = player_state.current_platform();
+
+        // Every fall counts against the jump the player was attempting,
+        // every landing further ahead counts as a cleared attempt.
+        let attempt = match update {
+            PlayerStateUpdate::TeleportedBack => Some((before + 1, true)),
+            PlayerStateUpdate::OnCourse | PlayerStateUpdate::Skipped(_) if after > before => {
+                Some((after, false))
+            }
+            _ => None,
+        };
+
+        if let Some((platform, failed)) = attempt {
+            self.course.record_attempt(platform, failed);
+
+            if let Some(jump) = self.course.jump(platform) {
+                player_state.record_attempt(jump.kind, failed);
+            }
+
+            self.stats_changed = true;
+        }
+
+        // Teammates respawn wherever the furthest of them got
+        if after > before {
+            let player_state = self.player_states.remove(&player_id).unwrap();
+
+            for teammate in self.teams.teammates(&player_id) {
+                if let Some(state) = self.player_states.get_mut(&teammate) {
+                    state.share_checkpoint(&player_state);
+                }
+            }
+
+            self.player_states.insert(player_id, player_state);
+        }
+
+        update
+    }
+
+    /// Takes a player off the course, e.g. when they go to another one.
+    fn remove_player(&mut self, player_id: &Uuid) {
+        self.player_states.remove(player_id);
+        self.teams.leave(player_id);
+        self.stats_changed = true;
+    }
+
+    /// Whether the player is the last of their team to finish. The team's time
+    /// is the time of that last member.
+    fn finish_team(&mut self, player_id: &Uuid) -> Option<(Text, Duration)> {
+        let team = self.teams.get_mut(player_id)?;
+
+        let all_finished = team.members.iter().all(|member| {
+            self.player_states
+                .get(member)
+                .map(|state| state.finished())
+                .unwrap_or(true)
+        });
+
+        if team.finished || !all_finished {
+            return None;
+        }
+
+        team.finished = true;
+
+        let time = team
+            .members
+            .iter()
+            .filter_map(|member| self.player_states.get(member))
+            .map(|state| state.elapsed())
+            .max()
+            .unwrap_or_default();
+
+        Some((team.name.clone().color(team.color), time))
+    }
+
+    fn team_color(&self, player_id: &Uuid) -> Option<Color> {
+        self.teams.get(player_id).map(|team| team.color)
+    }
+
+    /// Reweights the jumps by how the players on the course are doing, if
+    /// that changed.
+    fn adapt_difficulty(&mut self) {
+        if !self.adaptive || !std::mem::take(&mut self.stats_changed) {
+            return;
+        }
+
+        let mut stats: HashMap<JumpKind, JumpStats> = HashMap::new();
+        for state in self.player_states.values().filter(|state| !state.finished()) {
+            for (kind, kind_stats) in state.kind_stats() {
+                stats.entry(*kind).or_default().merge(kind_stats);
+            }
+        }
+
+        self.course.adapt_difficulty(&stats);
     }
 
     fn current_platform(&self, player_id: &Uuid) -> i32 {
@@ -76,6 +335,14 @@ impl GameState {
         self.course.done()
     }
 
+    /// Whether there is enough of the course, and of the terrain around its
+    /// start, for players to be let onto it.
+    fn ready(&self, layer: &ChunkLayer) -> bool {
+        let start = BlockPos::from(self.course.get_start());
+
+        (self.course.len() > READY_PLATFORMS || self.done()) && layer.chunk(start).is_some()
+    }
+
     fn finished(&self, player_id: &Uuid) -> bool {
         self.player_states
             .get(player_id)
@@ -98,6 +365,144 @@ impl GameState {
             .map(|state| state.to_last_checkpoint(pos, look));
     }
 
+    fn fall(&mut self, player_id: Uuid, pos: &mut Position, look: &mut Look) {
+        self.player_states
+            .get_mut(&player_id)
+            .map(|state| state.fall(pos, look));
+    }
+
+    fn falls(&self, player_id: &Uuid) -> u32 {
+        self.player_states
+            .get(player_id)
+            .map(|state| state.falls())
+            .unwrap_or(0)
+    }
+
+    fn falls_on_current_platform(&self, player_id: &Uuid) -> u32 {
+        self.player_states
+            .get(player_id)
+            .map(|state| state.falls_on(state.current_platform()))
+            .unwrap_or(0)
+    }
+
+    fn elapsed(&self, player_id: &Uuid) -> Duration {
+        self.player_states
+            .get(player_id)
+            .map(|state| state.elapsed())
+            .unwrap_or_default()
+    }
+
+    /// The players furthest along the course, best first.
+    fn leaders(&self, count: usize) -> Vec<(Uuid, i32)> {
+        let mut leaders = self
+            .player_states
+            .iter()
+            .map(|(player_id, state)| (*player_id, state.current_platform()))
+            .collect::<Vec<_>>();
+
+        leaders.sort_by_key(|(_, platform)| -platform);
+        leaders.truncate(count);
+
+        leaders
+    }
+
+    fn practicing(&self, player_id: &Uuid) -> bool {
+        self.player_states
+            .get(player_id)
+            .map(|state| state.practice())
+            .unwrap_or(false)
+    }
+
+    fn set_practice(&mut self, player_id: Uuid, practice: bool) {
+        self.player_states
+            .get_mut(&player_id)
+            .map(|state| state.set_practice(practice));
+    }
+
+    /// Moves a player and their checkpoint to a platform, as long as it's
+    /// built.
+    fn teleport_to_platform(
+        &mut self,
+        player_id: Uuid,
+        platform: i32,
+        pos: &mut Position,
+        look: &mut Look,
+    ) -> bool {
+        if !self.course.built_platforms().contains(&platform) {
+            return false;
+        }
+        let Some(jump) = self.course.jump(platform) else {
+            return false;
+        };
+        let Some(state) = self.player_states.get_mut(&player_id) else {
+            return false;
+        };
+
+        state.set_checkpoint(platform, jump.endpoint.center(), look.vec());
+        state.to_last_checkpoint(pos, look);
+
+        true
+    }
+
+    /// Makes the player's exact position their checkpoint, as long as they
+    /// are standing on a platform.
+    fn set_checkpoint(&mut self, player_id: Uuid, pos: &Position, look: &Look) -> Option<i32> {
+        let platform = self.course.platform_at(pos.get())?;
+        let state = self.player_states.get_mut(&player_id)?;
+
+        state.set_checkpoint(platform, pos.get(), look.vec());
+
+        Some(platform)
+    }
+
+    /// The run of a player as it would be recorded. Practice runs aren't.
+    fn run_record(&self, player_id: Uuid, username: &str) -> Option<RunRecord> {
+        let state = self.player_states.get(&player_id)?;
+        if state.practice() {
+            return None;
+        }
+
+        Some(RunRecord {
+            player: player_id,
+            username: username.to_owned(),
+            time: state.elapsed(),
+            falls: state.falls(),
+            platforms: self.course.len() - 1,
+            team: None,
+            course: Some(self.name.clone()),
+        })
+    }
+
+    /// Starts a fresh run for the player on a platform further along the
+    /// course, e.g. for their leg of a relay. Returns false if that platform
+    /// isn't generated yet.
+    fn start_player_at(&mut self, player_id: Uuid, platform: i32, pos: &mut Position) -> bool {
+        let Some(jump) = self.course.jump(platform) else {
+            return false;
+        };
+        let spawn = jump.endpoint.center();
+
+        self.player_states
+            .insert(player_id, PlayerOnCourse::starting_at(platform, spawn));
+
+        pos.set(spawn);
+        true
+    }
+
+    /// Whether a race, elimination round or relay is running on the course.
+    fn round_running(&self) -> bool {
+        self.race.is_some() || self.elimination.is_some() || !self.relays.is_empty()
+    }
+
+    /// Whether the player is sitting out a round, and can't play until it's
+    /// their turn or the round is over.
+    fn benched(&self, player_id: &Uuid) -> bool {
+        self.elimination
+            .as_ref()
+            .is_some_and(|elimination| elimination.is_eliminated(player_id))
+            || self.relays.iter().any(|relay| relay.is_waiting(player_id))
+    }
+
     fn pause(&mut self, player_id: Uuid) {
         self.player_states
             .get_mut(&player_id)
@@ -110,8 +515,23 @@ impl GameState {
             .map(|state| state.resume());
     }
 
+    /// Each course and seed gets its own file, so courses don't overwrite
+    /// each other's heatmaps.
+    fn heatmap_path(&self) -> PathBuf {
+        PathBuf::from(format!("{HEATMAP_PREFIX}-{}-{}.csv", self.name, self.course.seed()))
+    }
+
+    fn export_heatmap(&self) -> std::io::Result<()> {
+        stats::export_heatmap(&self.course, &self.heatmap_path())
+    }
+
     fn reset_course(&mut self, layer: &mut ChunkLayer) {
+        if let Err(e) = self.export_heatmap() {
+            eprintln!("failed to export the jump heatmap: {e:#}");
+        }
+
         self.course.reset(layer);
+        self.teams.reset();
     }
 }
 
@@ -145,9 +565,120 @@ struct Pause;
 #[scopes("parkour.command.resume")]
 struct Resume;
 
+#[derive(Command, Debug, Clone)]
+#[paths("spectate {target}")]
+#[scopes("parkour.command.spectate")]
+struct Spectate {
+    target: String,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("back {platforms}")]
+#[scopes("parkour.command.back")]
+struct Back {
+    platforms: Option<i32>,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("practice")]
+#[scopes("parkour.command.practice")]
+struct Practice;
+
+#[derive(Command, Debug, Clone)]
+#[paths("tp {platform}")]
+#[scopes("parkour.command.tp")]
+struct Teleport {
+    platform: i32,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("setcheckpoint")]
+#[scopes("parkour.command.setcheckpoint")]
+struct SetCheckpoint;
+
+#[derive(Command, Debug, Clone)]
+#[paths("courses")]
+#[scopes("parkour.command.courses")]
+struct Courses;
+
+#[derive(Command, Debug, Clone)]
+#[paths("join {course}")]
+#[scopes("parkour.command.join")]
+struct Join {
+    course: String,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("invite {player}")]
+#[scopes("parkour.command.invite")]
+struct Invite {
+    player: String,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("lobby", "leave")]
+#[scopes("parkour.command.lobby")]
+struct LobbyCommand;
+
+#[derive(Command, Debug, Clone)]
+#[paths("race")]
+#[scopes("parkour.moderate.race")]
+enum RaceCommand {
+    #[paths("start")]
+    Start,
+    #[paths("stop")]
+    Stop,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("elimination", "void")]
+#[scopes("parkour.moderate.elimination")]
+enum EliminationCommand {
+    #[paths("start")]
+    Start,
+    #[paths("stop")]
+    Stop,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("team")]
+#[scopes("parkour.command.team")]
+enum TeamCommand {
+    #[paths("create {name}")]
+    Create { name: String },
+    #[paths("join {name}")]
+    Join { name: String },
+    #[paths("leave")]
+    Leave,
+    #[paths("list")]
+    List,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("relay")]
+#[scopes("parkour.command.relay")]
+enum RelayCommand {
+    #[paths("start {leg_length}")]
+    Start { leg_length: i32 },
+    #[paths("stop")]
+    Stop,
+    #[paths("top")]
+    Top,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("jumpstats")]
+#[scopes("parkour.command.jumpstats")]
+enum JumpStatsCommand {
+    #[paths("{platform}")]
+    Platform { platform: i32 },
+    #[paths("export")]
+    Export,
+}
+
 #[derive(Command, Debug, Clone)]
 #[paths("gamemode", "gm")]
-#[scopes("parkour.command.gamemode")]
+#[scopes("parkour.moderate.gamemode")]
 enum GamemodeCommand {
     #[paths("creative", "{/} gmc")]
     Creative,
@@ -157,14 +688,112 @@ enum GamemodeCommand {
     Spectator,
 }
 
+#[derive(Command, Debug, Clone)]
+#[paths("whitelist")]
+#[scopes("parkour.moderate.whitelist")]
+enum WhitelistCommand {
+    #[paths("add {player}")]
+    Add { player: String },
+    #[paths("remove {player}")]
+    Remove { player: String },
+    #[paths("on")]
+    On,
+    #[paths("off")]
+    Off,
+    #[paths("list")]
+    List,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("ban {player} {reason}")]
+#[scopes("parkour.moderate.ban")]
+struct Ban {
+    player: String,
+    reason: Option<GreedyString>,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("pardon {player}")]
+#[scopes("parkour.moderate.pardon")]
+struct Pardon {
+    player: String,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("op {player}")]
+#[scopes("parkour.admin.op")]
+struct Op {
+    player: String,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("deop {player}")]
+#[scopes("parkour.admin.op")]
+struct Deop {
+    player: String,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("kick {player} {reason}")]
+#[scopes("parkour.moderate.kick")]
+struct Kick {
+    player: String,
+    reason: Option<GreedyString>,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("list")]
+#[scopes("parkour.admin.list")]
+struct List;
+
+#[derive(Command, Debug, Clone)]
+#[paths("regenerate {course}")]
+#[scopes("parkour.admin.regenerate")]
+struct Regenerate {
+    course: Option<String>,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("seed")]
+#[scopes("parkour.admin.seed")]
+struct Seed;
+
+#[derive(Command, Debug, Clone)]
+#[paths("reseed {course} {seed}")]
+#[scopes("parkour.admin.seed")]
+struct Reseed {
+    course: String,
+    seed: String,
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("save")]
+#[scopes("parkour.admin.save")]
+struct Save;
+
+#[derive(Command, Debug, Clone)]
+#[paths("stop")]
+#[scopes("parkour.admin.stop")]
+struct Stop;
+
 fn handle_help_command(
     mut events: EventReader<CommandResultEvent<Help>>,
-    mut clients: Query<(Entity, &mut Client)>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    executors: Query<&CommandScopes>,
+    scopes: Res<CommandScopeRegistry>,
 ) {
     for event in events.read() {
-        let author = &mut clients.get_mut(event.executor).unwrap().1;
+        let granted = |scope: &str| {
+            executors
+                .get(event.executor)
+                .is_ok_and(|executor_scopes| scopes.any_grants(&executor_scopes.0, scope))
+        };
 
-        author.send_chat_message(
+        reply(
+            &mut clients,
+            &remotes,
+            event.executor,
             "Welcome to the tini parkour server :3\n\n"
                 .color(Color::YELLOW)
                 .bold()
@@ -185,6 +814,36 @@ fn handle_help_command(
                 + " - get the course status, i.e. the number of platforms left.\n- "
                     .color(Color::WHITE)
                     .not_bold()
+                + "/courses"
+                    .on_click_run_command("/courses")
+                    .on_hover_show_text("Click to run.")
+                    .color(Color::AQUA)
+                    .bold()
+                + " - list the courses you can join.\n- "
+                    .color(Color::WHITE)
+                    .not_bold()
+                + "/join"
+                    .on_click_suggest_command("/join ")
+                    .on_hover_show_text("Click to suggest.")
+                    .color(Color::AQUA)
+                    .bold()
+                + " - join a course.\n- ".color(Color::WHITE).not_bold()
+                + "/lobby"
+                    .on_click_run_command("/lobby")
+                    .on_hover_show_text("Click to run.")
+                    .color(Color::AQUA)
+                    .bold()
+                + " - leave your course and go back to the lobby.\n- "
+                    .color(Color::WHITE)
+                    .not_bold()
+                + "/invite"
+                    .on_click_suggest_command("/invite ")
+                    .on_hover_show_text("Click to suggest.")
+                    .color(Color::AQUA)
+                    .bold()
+                + " - bring another player onto your own copy of a course.\n- "
+                    .color(Color::WHITE)
+                    .not_bold()
                 + "/reset"
                     .on_click_run_command("/reset")
                     .on_hover_show_text("Click to run.")
@@ -215,33 +874,214 @@ fn handle_help_command(
                     .color(Color::AQUA)
                     .bold()
                 + "- resume the game.\n- ".color(Color::WHITE).not_bold()
-                + "/gamemode"
-                    .on_click_run_command("/gamemode")
-                    .on_hover_show_text("Click to run.")
+                + "/jumpstats"
+                    .on_click_suggest_command("/jumpstats ")
+                    .on_hover_show_text("Click to suggest.")
+                    .color(Color::AQUA)
+                    .bold()
+                + " - see how often a jump has been failed.\n- "
+                    .color(Color::WHITE)
+                    .not_bold()
+                + "/team"
+                    .on_click_suggest_command("/team create ")
+                    .on_hover_show_text("Click to suggest.")
                     .color(Color::AQUA)
                     .bold()
-                + " - change your gamemode. Automatically pauses the game."
+                + " - play as a team with shared checkpoints.\n- "
                     .color(Color::WHITE)
-                    .not_bold(),
+                    .not_bold()
+                + "/relay"
+                    .on_click_suggest_command("/relay start 20")
+                    .on_hover_show_text("Click to suggest.")
+                    .color(Color::AQUA)
+                    .bold()
+                + " - run the course as a relay with your team, ".color(Color::WHITE).not_bold()
+                + "/relay top".color(Color::AQUA).bold()
+                + " for the fastest ones.\n- ".color(Color::WHITE).not_bold()
+                + "/back"
+                    .on_click_run_command("/back")
+                    .on_hover_show_text("Click to run.")
+                    .color(Color::AQUA)
+                    .bold()
+                + " - go back a platform, or ".color(Color::WHITE).not_bold()
+                + "/back <n>".color(Color::AQUA).bold()
+                + " platforms.\n- ".color(Color::WHITE).not_bold()
+                + "/practice"
+                    .on_click_run_command("/practice")
+                    .on_hover_show_text("Click to run.")
+                    .color(Color::AQUA)
+                    .bold()
+                + " - toggle practice mode, where you can use ".color(Color::WHITE).not_bold()
+                + "/tp <platform>".color(Color::AQUA).bold()
+                + " and ".color(Color::WHITE).not_bold()
+                + "/setcheckpoint".color(Color::AQUA).bold()
+                + ". Practice runs aren't recorded.\n- ".color(Color::WHITE).not_bold()
+                + "/spectate"
+                    .on_click_suggest_command("/spectate ")
+                    .on_hover_show_text("Click to suggest.")
+                    .color(Color::AQUA)
+                    .bold()
+                + " - watch another player's run, ".color(Color::WHITE).not_bold()
+                + "/resume".color(Color::AQUA).bold()
+                + " to get back.".color(Color::WHITE).not_bold(),
         );
+
+        if granted("parkour.moderate") {
+            reply(
+                &mut clients,
+                &remotes,
+                event.executor,
+                "\nModerators can also use:\n".color(Color::YELLOW).bold()
+                    + "- ".color(Color::WHITE).not_bold()
+                    + "/race"
+                        .on_click_suggest_command("/race start")
+                        .on_hover_show_text("Click to suggest.")
+                        .color(Color::AQUA)
+                        .bold()
+                    + " - start or stop a race on your course.\n- "
+                        .color(Color::WHITE)
+                        .not_bold()
+                    + "/elimination"
+                        .on_click_suggest_command("/elimination start")
+                        .on_hover_show_text("Click to suggest.")
+                        .color(Color::AQUA)
+                        .bold()
+                    + " - outrun the rising void, last one standing wins.\n- "
+                        .color(Color::WHITE)
+                        .not_bold()
+                    + "/gamemode"
+                        .on_click_run_command("/gamemode")
+                        .on_hover_show_text("Click to run.")
+                        .color(Color::AQUA)
+                        .bold()
+                    + " - change your gamemode. Automatically pauses the game.\n- "
+                        .color(Color::WHITE)
+                        .not_bold()
+                    + "/whitelist".color(Color::AQUA).bold()
+                    + ", ".color(Color::WHITE).not_bold()
+                    + "/ban".color(Color::AQUA).bold()
+                    + " and ".color(Color::WHITE).not_bold()
+                    + "/pardon".color(Color::AQUA).bold()
+                    + " - manage who can join.\n- ".color(Color::WHITE).not_bold()
+                    + "/kick".color(Color::AQUA).bold()
+                    + " - disconnect a player.".color(Color::WHITE).not_bold(),
+            );
+        }
+
+        if granted("parkour.admin") {
+            reply(
+                &mut clients,
+                &remotes,
+                event.executor,
+                "\nAdmins can also use:\n".color(Color::YELLOW).bold()
+                    + "- ".color(Color::WHITE).not_bold()
+                    + "/op".color(Color::AQUA).bold()
+                    + " and ".color(Color::WHITE).not_bold()
+                    + "/deop".color(Color::AQUA).bold()
+                    + " - manage the operators.\n- ".color(Color::WHITE).not_bold()
+                    + "/list".color(Color::AQUA).bold()
+                    + " - see who is online and how far they got.\n- "
+                        .color(Color::WHITE)
+                        .not_bold()
+                    + "/regenerate".color(Color::AQUA).bold()
+                    + " - regenerate a course, or all of them.\n- "
+                        .color(Color::WHITE)
+                        .not_bold()
+                    + "/jumpstats export".color(Color::AQUA).bold()
+                    + " - write the fail heatmap of your course to disk.\n- "
+                        .color(Color::WHITE)
+                        .not_bold()
+                    + "/seed".color(Color::AQUA).bold()
+                    + " - see the seed of every course.\n- "
+                        .color(Color::WHITE)
+                        .not_bold()
+                    + "/reseed".color(Color::AQUA).bold()
+                    + " - regenerate a course from a new seed.\n- "
+                        .color(Color::WHITE)
+                        .not_bold()
+                    + "/save".color(Color::AQUA).bold()
+                    + " - write the jump statistics to disk.\n- "
+                        .color(Color::WHITE)
+                        .not_bold()
+                    + "/stop".color(Color::AQUA).bold()
+                    + " - save and shut down the server.".color(Color::WHITE).not_bold(),
+            );
+        }
+    }
+}
+
+/// A clickable list of all the course instances.
+fn course_list(instances: &[InstanceStatus]) -> Text {
+    let mut list = "Courses:".color(Color::YELLOW).bold();
+
+    for instance in instances {
+        list = list
+            + "\n- ".color(Color::WHITE).not_bold()
+            + instance
+                .name
+                .clone()
+                .on_click_run_command(format!("/join {}", instance.name))
+                .on_hover_show_text("Click to join.")
+                .color(Color::AQUA)
+                .bold()
+            + format!(
+                " ({}, {} players, {} platforms{})",
+                instance.difficulty,
+                instance.players,
+                instance.platforms,
+                if instance.generating {
+                    ", generating"
+                } else {
+                    ""
+                }
+            )
+            .color(Color::WHITE)
+            .not_bold();
+    }
+
+    list
+}
+
+fn handle_courses_command(
+    mut events: EventReader<CommandResultEvent<Courses>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    status: Res<ServerStatus>,
+) {
+    for event in events.read() {
+        reply(&mut clients, &remotes, event.executor, course_list(&status.instances()));
     }
 }
 
 fn handle_info_command(
     mut events: EventReader<CommandResultEvent<Info>>,
-    mut clients: Query<(Entity, &mut Client)>,
-    mut players: Query<(Entity, &mut UniqueId)>,
-    state: Query<&GameState>,
+    mut clients: Query<(&mut Client, &UniqueId, &VisibleChunkLayer)>,
+    states: Query<(&GameState, &CourseInstance)>,
+    records: Res<Records>,
+    status: Res<ServerStatus>,
+    remotes: Query<&RemoteExecutor>,
 ) {
-    let state = state.single();
-
     for event in events.read() {
-        let author = &players.get_mut(event.executor).unwrap().1;
-        let client = &mut clients.get_mut(event.executor).unwrap().1;
+        let Ok((mut client, author, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let Ok((state, instance)) = states.get(layer.0) else {
+            client.send_chat_message(
+                "You are in the lobby.\n\n".color(Color::YELLOW).bold()
+                    + course_list(&status.instances()),
+            );
+            continue;
+        };
 
         client.send_chat_message(
             "Course status:\n\n".color(Color::YELLOW).bold()
-                + "Platforms generated ahead: ".color(Color::WHITE).not_bold()
+                + "Course: ".color(Color::WHITE).not_bold()
+                + instance.name.clone().color(Color::LIGHT_PURPLE).bold()
+                + format!(" ({})", state.course.preset().name())
+                    .color(Color::WHITE)
+                    .not_bold()
+                + "\nPlatforms generated ahead: ".color(Color::WHITE).not_bold()
                 + (state.platforms_left(&author.0) - 1)
                     .color(Color::LIGHT_PURPLE)
                     .bold()
@@ -252,60 +1092,114 @@ fn handle_info_command(
                     .bold()
                 + "\nTotal platform count: ".color(Color::WHITE).not_bold()
                 + (state.course.len() - 1).color(Color::LIGHT_PURPLE).bold()
-                + if state.done() {
-                    "\n\nThe course has finished generating."
-                        .color(Color::GREEN)
-                        .not_bold()
+                + "\nSeed: ".color(Color::WHITE).not_bold()
+                + state
+                    .course
+                    .seed()
+                    .to_string()
+                    .color(Color::LIGHT_PURPLE)
+                    .bold()
+                + "\nFalls this run: ".color(Color::WHITE).not_bold()
+                + state.falls(&author.0).color(Color::LIGHT_PURPLE).bold()
+                + "\nFalls on this platform: ".color(Color::WHITE).not_bold()
+                + state
+                    .falls_on_current_platform(&author.0)
+                    .color(Color::LIGHT_PURPLE)
+                    .bold()
+                + "\nBest run: ".color(Color::WHITE).not_bold()
+                + match records.best(&author.0) {
+                    Some(record) => format!(
+                        "{} ({} falls)",
+                        format_duration(record.time),
+                        record.falls
+                    )
+                    .color(Color::LIGHT_PURPLE)
+                    .bold(),
+                    None => "none yet".color(Color::GRAY).not_bold(),
+                }
+                + match state
+                    .teams
+                    .get(&author.0)
+                    .and_then(|team| records.best_relay(&team.name))
+                {
+                    Some(record) => "\nBest relay of your team: ".color(Color::WHITE).not_bold()
+                        + format_duration(record.time)
+                            .color(Color::LIGHT_PURPLE)
+                            .bold(),
+                    None => Text::default(),
+                }
+                + if state.done() {
+                    "\n\nThe course has finished generating.\n\n"
+                        .color(Color::GREEN)
+                        .not_bold()
+                } else if state.course.endless() {
+                    "\n\nThe course is still generating. There will be more platforms.\n\n"
+                        .color(Color::YELLOW)
+                        .not_bold()
                 } else {
-                    "\n\nThe course is still generating. There will be more platforms."
+                    "\n\nThe course is still generating up to its full length.\n\n"
                         .color(Color::YELLOW)
                         .not_bold()
-                },
+                }
+                + course_list(&status.instances()),
         );
     }
 }
 
 fn handle_reset_command(
     mut events: EventReader<CommandResultEvent<Reset>>,
-    mut clients: Query<(Entity, &mut Position, &UniqueId)>,
-    mut state: Query<&mut GameState>,
+    mut clients: Query<(&mut Position, &UniqueId, &VisibleChunkLayer)>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
 ) {
-    let mut state = state.single_mut();
-
     for event in events.read() {
-        let player_id = clients.get(event.executor).unwrap().2 .0;
-        let mut author_position = clients.get_mut(event.executor).unwrap().1;
+        let Ok((mut author_position, player_id, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            continue;
+        };
 
-        state.reset_player(player_id, author_position.as_mut());
+        state.reset_player(player_id.0, author_position.as_mut());
     }
 }
 
 fn handle_kill_command(
     mut events: EventReader<CommandResultEvent<Kill>>,
-    mut clients: Query<(Entity, &mut Position, &mut Look, &UniqueId)>,
-    mut state: Query<&mut GameState>,
+    mut clients: Query<(&mut Position, &mut Look, &UniqueId, &VisibleChunkLayer)>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
 ) {
-    let mut state = state.single_mut();
-
     for event in events.read() {
-        let player_id = clients.get(event.executor).unwrap().3 .0;
-        let author = clients.get_mut(event.executor).unwrap();
-        let mut author_position = author.1;
-        let mut author_look = author.2;
+        let Ok((mut author_position, mut author_look, player_id, layer)) =
+            clients.get_mut(event.executor)
+        else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            continue;
+        };
 
-        state.to_last_checkpoint(player_id, author_position.as_mut(), author_look.as_mut());
+        state.fall(player_id.0, author_position.as_mut(), author_look.as_mut());
     }
 }
 
 fn handle_pause_command(
     mut events: EventReader<CommandResultEvent<Pause>>,
-    clients: Query<&UniqueId>,
-    mut state: Query<&mut GameState>,
+    clients: Query<(&UniqueId, &VisibleChunkLayer)>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
 ) {
-    let mut state = state.single_mut();
-
     for event in events.read() {
-        let player_id = clients.get(event.executor).unwrap();
+        let Ok((player_id, layer)) = clients.get(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            continue;
+        };
 
         state.pause(player_id.0);
     }
@@ -313,14 +1207,51 @@ fn handle_pause_command(
 
 fn handle_resume_command(
     mut events: EventReader<CommandResultEvent<Resume>>,
-    mut clients: Query<(&mut GameMode, &UniqueId, &mut Position, &mut Look, Entity)>,
-    mut state: Query<&mut GameState>,
+    mut clients: Query<(
+        &mut Client,
+        &mut GameMode,
+        &UniqueId,
+        &EntityId,
+        &mut Position,
+        &mut Look,
+        &VisibleChunkLayer,
+        Has<Spectating>,
+    )>,
+    mut states: Query<&mut GameState>,
+    mut commands: Commands,
+    remotes: Query<&RemoteExecutor>,
 ) {
-    let mut state = state.single_mut();
-
     for event in events.read() {
-        let (mut gamemode, player_id, mut pos, mut look, ..) =
-            clients.get_mut(event.executor).unwrap();
+        let Ok((
+            mut client,
+            mut gamemode,
+            player_id,
+            entity_id,
+            mut pos,
+            mut look,
+            layer,
+            spectating,
+        )) = clients.get_mut(event.executor)
+        else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+
+        if spectating {
+            set_camera(&mut client, entity_id);
+            commands.entity(event.executor).remove::<Spectating>();
+        }
+
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            continue;
+        };
+
+        if state.benched(&player_id.0) {
+            client.send_chat_message(
+                "You can't play until it's your turn or the round is over.".color(Color::RED),
+            );
+            continue;
+        }
 
         *gamemode = GameMode::Adventure;
 
@@ -329,15 +1260,1024 @@ fn handle_resume_command(
     }
 }
 
+fn handle_jumpstats_command(
+    mut events: EventReader<CommandResultEvent<JumpStatsCommand>>,
+    mut clients: Query<(&mut Client, &VisibleChunkLayer)>,
+    states: Query<&GameState>,
+    executors: Query<&CommandScopes>,
+    scopes: Res<CommandScopeRegistry>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let Ok(state) = states.get(layer.0) else {
+            continue;
+        };
+
+        match &event.result {
+            // Writing to disk is up to admins, like `/save`
+            JumpStatsCommand::Export
+                if !executors.get(event.executor).is_ok_and(|executor_scopes| {
+                    scopes.any_grants(&executor_scopes.0, JUMPSTATS_EXPORT_SCOPE)
+                }) =>
+            {
+                client.send_chat_message(
+                    "Only admins can export the jump stats.".color(Color::RED),
+                );
+            }
+            JumpStatsCommand::Platform { platform } => {
+                let Some(jump) = state.course.jump(*platform) else {
+                    client.send_chat_message(
+                        "There is no platform ".color(Color::RED)
+                            + platform.color(Color::LIGHT_PURPLE)
+                            + " yet.".color(Color::RED),
+                    );
+                    continue;
+                };
+
+                client.send_chat_message(
+                    "Jump to platform ".color(Color::YELLOW).bold()
+                        + platform.color(Color::LIGHT_PURPLE).bold()
+                        + format!(" ({})", jump.kind.name())
+                            .color(Color::YELLOW)
+                            .bold()
+                        + "\n\nAttempts: ".color(Color::WHITE).not_bold()
+                        + jump.stats.attempts.color(Color::LIGHT_PURPLE).bold()
+                        + "\nFails: ".color(Color::WHITE).not_bold()
+                        + jump.stats.fails.color(Color::LIGHT_PURPLE).bold()
+                        + "\nFail rate: ".color(Color::WHITE).not_bold()
+                        + match jump.stats.fail_rate() {
+                            Some(rate) => format!("{:.0}%", rate * 100.0)
+                                .color(Color::LIGHT_PURPLE)
+                                .bold(),
+                            None => "no attempts yet".color(Color::GRAY).not_bold(),
+                        },
+                );
+            }
+            JumpStatsCommand::Export => match state.export_heatmap() {
+                Ok(()) => client.send_chat_message(
+                    "Exported the fail heatmap to ".color(Color::GREEN)
+                        + state.heatmap_path().display().to_string().color(Color::AQUA)
+                        + ".".color(Color::GREEN),
+                ),
+                Err(e) => client.send_chat_message(
+                    format!("Failed to export the fail heatmap: {e}").color(Color::RED),
+                ),
+            },
+        }
+    }
+}
+
+fn handle_race_command(
+    mut events: EventReader<CommandResultEvent<RaceCommand>>,
+    mut clients: Query<(&mut Client, &VisibleChunkLayer)>,
+    players: Query<(&UniqueId, &Username, &VisibleChunkLayer)>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let course = layer.0;
+
+        let Ok(mut state) = states.get_mut(course) else {
+            client.send_chat_message("Join a course to race on it.".color(Color::RED));
+            continue;
+        };
+
+        let announcement = match &event.result {
+            RaceCommand::Start => {
+                if state.round_running() {
+                    client.send_chat_message(
+                        "A round is already running on this course.".color(Color::RED),
+                    );
+                    continue;
+                }
+
+                let participants = players
+                    .iter()
+                    .filter(|(_, _, layer)| layer.0 == course)
+                    .map(|(player_id, username, _)| (player_id.0, username.0.clone()))
+                    .collect::<Vec<_>>();
+
+                let announcement = "A race is starting with ".color(Color::YELLOW)
+                    + participants.len().color(Color::LIGHT_PURPLE)
+                    + " players!".color(Color::YELLOW);
+
+                state.race = Some(Race::new(participants));
+
+                announcement
+            }
+            RaceCommand::Stop => {
+                let Some(race) = state.race.take() else {
+                    client.send_chat_message("There is no race running.".color(Color::RED));
+                    continue;
+                };
+
+                race.standings(&state.player_states)
+            }
+        };
+
+        for (mut client, layer) in &mut clients {
+            if layer.0 == course {
+                client.send_chat_message(announcement.clone());
+            }
+        }
+    }
+}
+
+fn handle_elimination_command(
+    mut events: EventReader<CommandResultEvent<EliminationCommand>>,
+    mut clients: Query<(
+        &mut Client,
+        &mut Position,
+        &mut GameMode,
+        &UniqueId,
+        &Username,
+        &VisibleChunkLayer,
+    )>,
+    mut states: Query<(&mut GameState, &mut ChunkLayer)>,
+    settings: Res<Settings>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, .., layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let course = layer.0;
+
+        let Ok((mut state, mut chunk_layer)) = states.get_mut(course) else {
+            client.send_chat_message("Join a course to start a round on it.".color(Color::RED));
+            continue;
+        };
+
+        let announcement = match &event.result {
+            EliminationCommand::Start => {
+                if state.round_running() {
+                    client.send_chat_message(
+                        "A round is already running on this course.".color(Color::RED),
+                    );
+                    continue;
+                }
+
+                let players = clients
+                    .iter()
+                    .filter(|(.., layer)| layer.0 == course)
+                    .map(|(.., player_id, username, _)| (player_id.0, username.0.clone()))
+                    .collect::<Vec<_>>();
+
+                if players.len() < 2 {
+                    let (mut client, ..) = clients.get_mut(event.executor).unwrap();
+                    client.send_chat_message(
+                        "An elimination round needs at least 2 players.".color(Color::RED),
+                    );
+                    continue;
+                }
+
+                state.elimination = Some(Elimination::new(
+                    players,
+                    settings.void_rate,
+                    settings.crumble,
+                ));
+
+                "The void is rising! Stay ahead of it.".color(Color::RED).bold()
+            }
+            EliminationCommand::Stop => {
+                let Some(elimination) = state.elimination.take() else {
+                    client.send_chat_message("There is no round running.".color(Color::RED));
+                    continue;
+                };
+
+                elimination.results()
+            }
+        };
+
+        // Everyone starts the round, or gets back into the game after it, at
+        // the start of the course
+        for (mut client, mut pos, mut game_mode, player_id, _, layer) in &mut clients {
+            if layer.0 == course {
+                state.reset_player(player_id.0, pos.as_mut());
+                *game_mode = GameMode::Adventure;
+
+                client.send_chat_message(announcement.clone());
+            }
+        }
+
+        // Like at the natural end of a round, so crumbled platforms come back
+        if matches!(event.result, EliminationCommand::Stop) {
+            state.reset_course(&mut chunk_layer);
+        }
+    }
+}
+
+fn handle_team_command(
+    mut events: EventReader<CommandResultEvent<TeamCommand>>,
+    mut clients: Query<(&mut Client, &UniqueId, &VisibleChunkLayer)>,
+    usernames: Query<(&UniqueId, &Username)>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, player_id, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            client.send_chat_message("Join a course to play in a team.".color(Color::RED));
+            continue;
+        };
+
+        let result = match &event.result {
+            TeamCommand::Create { name } => state
+                .teams
+                .create(name, player_id.0)
+                .map(|team| "Created team ".color(Color::GREEN) + name.clone().color(team.color)),
+            TeamCommand::Join { name } => state
+                .teams
+                .join(name, player_id.0)
+                .map(|team| "Joined team ".color(Color::GREEN) + name.clone().color(team.color)),
+            TeamCommand::Leave => state
+                .teams
+                .leave(&player_id.0)
+                .map(|name| "Left team ".color(Color::GREEN) + name.color(Color::AQUA))
+                .ok_or_else(|| "You are not in a team.".to_owned()),
+            TeamCommand::List => {
+                let mut list = "Teams on this course:".color(Color::YELLOW).bold();
+
+                for team in state.teams.iter() {
+                    let members = team
+                        .members
+                        .iter()
+                        .filter_map(|member| {
+                            usernames
+                                .iter()
+                                .find(|(id, _)| id.0 == *member)
+                                .map(|(_, username)| username.0.clone())
+                        })
+                        .collect::<Vec<_>>();
+
+                    list = list
+                        + "\n- ".color(Color::WHITE).not_bold()
+                        + team.name.clone().color(team.color)
+                        + format!(": {}", members.join(", ")).color(Color::GRAY);
+                }
+
+                Ok(list)
+            }
+        };
+
+        match result {
+            Ok(message) => client.send_chat_message(message),
+            Err(errmsg) => client.send_chat_message(errmsg.color(Color::RED)),
+        }
+    }
+}
+
+fn handle_relay_command(
+    mut events: EventReader<CommandResultEvent<RelayCommand>>,
+    mut clients: Query<(
+        &mut Client,
+        &mut Position,
+        &mut GameMode,
+        &UniqueId,
+        &VisibleChunkLayer,
+    )>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
+    records: Res<Records>,
+) {
+    for event in events.read() {
+        let Ok((mut client, _, _, player_id, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let player_id = player_id.0;
+
+        // Anyone can look at the leaderboard, in a team or not
+        if let RelayCommand::Top = event.result {
+            client.send_chat_message(relay_leaderboard(&records));
+            continue;
+        }
+        let course = layer.0;
+
+        let Ok(mut state) = states.get_mut(course) else {
+            client.send_chat_message("Join a course to run a relay on it.".color(Color::RED));
+            continue;
+        };
+
+        let Some(team) = state.teams.get(&player_id) else {
+            client.send_chat_message("You need to be in a team to run a relay.".color(Color::RED));
+            continue;
+        };
+        let team_name = team.name.clone();
+        let members = team.members.clone();
+
+        match &event.result {
+            RelayCommand::Start { leg_length } => {
+                if state.race.is_some() || state.elimination.is_some() {
+                    client.send_chat_message(
+                        "A round is already running on this course.".color(Color::RED),
+                    );
+                    continue;
+                }
+
+                if state.relays.iter().any(|relay| relay.team == team_name) {
+                    client.send_chat_message(
+                        "Your team is already running a relay.".color(Color::RED),
+                    );
+                    continue;
+                }
+
+                if members.len() < 2 || *leg_length < 1 {
+                    client.send_chat_message(
+                        "A relay needs at least 2 runners and legs of at least 1 platform."
+                            .color(Color::RED),
+                    );
+                    continue;
+                }
+
+                // Every leg has to start on a platform the course has
+                let relay_length = leg_length.saturating_mul(members.len() as i32);
+                let course_length = state.course.length().unwrap_or(i32::MAX);
+                if relay_length > course_length {
+                    client.send_chat_message(
+                        format!(
+                            "The course is only {course_length} platforms long, too short for {} \
+                             legs of {leg_length}.",
+                            members.len()
+                        )
+                        .color(Color::RED),
+                    );
+                    continue;
+                }
+
+                let relay = Relay::new(team_name.clone(), members, *leg_length);
+
+                for (mut client, mut pos, mut game_mode, member_id, _) in &mut clients {
+                    if !relay.runners().contains(&member_id.0) {
+                        continue;
+                    }
+
+                    state.reset_player(member_id.0, pos.as_mut());
+
+                    if relay.is_waiting(&member_id.0) {
+                        state.pause(member_id.0);
+                        *game_mode = GameMode::Spectator;
+                    } else {
+                        *game_mode = GameMode::Adventure;
+                    }
+
+                    client.send_chat_message(
+                        "Team ".color(Color::YELLOW)
+                            + team_name.clone().color(Color::AQUA)
+                            + format!(" is running a relay with {}", relay.legs())
+                                .color(Color::YELLOW)
+                            + format!(" legs of {leg_length} platforms!").color(Color::YELLOW),
+                    );
+                }
+
+                state.relays.push(relay);
+            }
+            RelayCommand::Stop => {
+                let Some(i) = state.relays.iter().position(|relay| relay.team == team_name)
+                else {
+                    client.send_chat_message(
+                        "Your team isn't running a relay.".color(Color::RED),
+                    );
+                    continue;
+                };
+
+                let relay = state.relays.remove(i);
+
+                for (mut client, mut pos, mut game_mode, member_id, _) in &mut clients {
+                    if relay.runners().contains(&member_id.0) {
+                        state.reset_player(member_id.0, pos.as_mut());
+                        *game_mode = GameMode::Adventure;
+
+                        client.send_chat_message("The relay was stopped.".color(Color::YELLOW));
+                    }
+                }
+            }
+            RelayCommand::Top => {}
+        }
+    }
+}
+
+/// The fastest relays, one per team.
+fn relay_leaderboard(records: &Records) -> Text {
+    let relays = records.best_relays(RELAY_LEADERBOARD_SIZE);
+    if relays.is_empty() {
+        return "No team has finished a relay yet.".color(Color::GRAY);
+    }
+
+    relays.iter().enumerate().fold(
+        "Fastest relays:".color(Color::YELLOW).bold(),
+        |text, (i, record)| {
+            text + format!("\n{}. ", i + 1).color(Color::GRAY).not_bold()
+                + record.username.clone().color(Color::AQUA).not_bold()
+                + " "
+                + format_duration(record.time).color(Color::GOLD).not_bold()
+        },
+    )
+}
+
+fn handle_back_command(
+    mut events: EventReader<CommandResultEvent<Back>>,
+    mut clients: Query<(
+        &mut Client,
+        &mut Position,
+        &mut Look,
+        &UniqueId,
+        &VisibleChunkLayer,
+    )>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, mut pos, mut look, player_id, layer)) =
+            clients.get_mut(event.executor)
+        else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            continue;
+        };
+
+        let platforms = event.result.platforms.unwrap_or(1);
+        if platforms < 1 {
+            client.send_chat_message("You can only go back 1 or more platforms.".color(Color::RED));
+            continue;
+        }
+
+        let platform = (state.current_platform(&player_id.0) - platforms).max(0);
+        if !state.teleport_to_platform(player_id.0, platform, pos.as_mut(), look.as_mut()) {
+            client.send_chat_message(
+                format!("Platform {platform} isn't there to go back to.").color(Color::RED),
+            );
+            continue;
+        }
+
+        client.send_action_bar_message(
+            "Back to platform ".into_text() + platform.color(Color::LIGHT_PURPLE),
+        );
+    }
+}
+
+fn handle_practice_command(
+    mut events: EventReader<CommandResultEvent<Practice>>,
+    mut clients: Query<(&mut Client, &mut Position, &UniqueId, &VisibleChunkLayer)>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, mut pos, player_id, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            client.send_chat_message("Join a course to practice on it.".color(Color::RED));
+            continue;
+        };
+
+        if state.practicing(&player_id.0) {
+            // Back to a real run, which has to start from the beginning
+            state.reset_player(player_id.0, pos.as_mut());
+
+            client.send_chat_message(
+                "Practice mode is off. Your run starts over.".color(Color::YELLOW),
+            );
+        } else {
+            state.set_practice(player_id.0, true);
+
+            client.send_chat_message(
+                "Practice mode is on. Use ".color(Color::YELLOW)
+                    + "/tp <platform>".on_click_suggest_command("/tp ").color(Color::AQUA)
+                    + " and ".color(Color::YELLOW)
+                    + "/setcheckpoint"
+                        .on_click_run_command("/setcheckpoint")
+                        .color(Color::AQUA)
+                    + ". This run won't be recorded.".color(Color::YELLOW),
+            );
+        }
+    }
+}
+
+fn handle_teleport_command(
+    mut events: EventReader<CommandResultEvent<Teleport>>,
+    mut clients: Query<(
+        &mut Client,
+        &mut Position,
+        &mut Look,
+        &UniqueId,
+        &VisibleChunkLayer,
+    )>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, mut pos, mut look, player_id, layer)) =
+            clients.get_mut(event.executor)
+        else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            continue;
+        };
+
+        if !state.practicing(&player_id.0) {
+            client.send_chat_message(
+                "You can only teleport in ".color(Color::RED)
+                    + "/practice".on_click_run_command("/practice").color(Color::AQUA)
+                    + " mode.".color(Color::RED),
+            );
+            continue;
+        }
+
+        let platform = event.result.platform;
+        if !state.teleport_to_platform(player_id.0, platform, pos.as_mut(), look.as_mut()) {
+            client.send_chat_message(
+                format!("Platform {platform} isn't there to teleport to.").color(Color::RED),
+            );
+        }
+    }
+}
+
+fn handle_setcheckpoint_command(
+    mut events: EventReader<CommandResultEvent<SetCheckpoint>>,
+    mut clients: Query<(&mut Client, &Position, &Look, &UniqueId, &VisibleChunkLayer)>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, pos, look, player_id, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            continue;
+        };
+
+        if !state.practicing(&player_id.0) {
+            client.send_chat_message(
+                "You can only set checkpoints in ".color(Color::RED)
+                    + "/practice".on_click_run_command("/practice").color(Color::AQUA)
+                    + " mode.".color(Color::RED),
+            );
+            continue;
+        }
+
+        match state.set_checkpoint(player_id.0, pos, look) {
+            Some(platform) => client.send_chat_message(
+                "Checkpoint set on platform ".color(Color::GREEN)
+                    + platform.color(Color::LIGHT_PURPLE),
+            ),
+            None => client.send_chat_message(
+                "Stand on a platform to set a checkpoint there.".color(Color::RED),
+            ),
+        }
+    }
+}
+
+/// Completes platform numbers, the only arguments clients ask the server about.
+fn handle_completion_requests(
+    mut packets: EventReader<PacketEvent>,
+    mut clients: Query<(&mut Client, &VisibleChunkLayer)>,
+    states: Query<&GameState>,
+) {
+    for packet in packets.read() {
+        let Some(request) = packet.decode::<RequestCommandCompletionsC2s>() else {
+            continue;
+        };
+        let Ok((mut client, layer)) = clients.get_mut(packet.client) else {
+            continue;
+        };
+        let platforms = states
+            .get(layer.0)
+            .map(|state| state.course.built_platforms())
+            .unwrap_or_default();
+
+        let text = request.text.0;
+        let start = text.rfind(' ').map(|i| i + 1).unwrap_or(text.len());
+        let typed = &text[start..];
+
+        let suggestions = platform_suggestions(typed, platforms);
+
+        client.write_packet(&CommandSuggestionsS2c {
+            id: request.transaction_id,
+            start: VarInt(start as i32),
+            length: VarInt(typed.len() as i32),
+            matches: suggestions
+                .iter()
+                .map(|suggestion| CommandSuggestionsMatch {
+                    suggested_match: suggestion,
+                    tooltip: None,
+                })
+                .collect(),
+        });
+    }
+}
+
+fn handle_spectate_command(
+    mut events: EventReader<CommandResultEvent<Spectate>>,
+    mut clients: Query<(&mut Client, &mut GameMode, &UniqueId, &VisibleChunkLayer)>,
+    players: Query<(Entity, &Username, &EntityId, &VisibleChunkLayer)>,
+    mut states: Query<&mut GameState>,
+    mut commands: Commands,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, mut game_mode, player_id, layer)) =
+            clients.get_mut(event.executor)
+        else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+
+        let Ok(mut state) = states.get_mut(layer.0) else {
+            client.send_chat_message("Join a course to spectate on it.".color(Color::RED));
+            continue;
+        };
+
+        let Some((target, username, target_id, target_layer)) = players
+            .iter()
+            .find(|(_, username, ..)| username.0.eq_ignore_ascii_case(&event.result.target))
+        else {
+            client.send_chat_message(
+                "There is no player called ".color(Color::RED)
+                    + event.result.target.clone().color(Color::AQUA)
+                    + ".".color(Color::RED),
+            );
+            continue;
+        };
+
+        if target == event.executor {
+            client.send_chat_message("You can't spectate yourself.".color(Color::RED));
+            continue;
+        }
+
+        if target_layer.0 != layer.0 {
+            client.send_chat_message(
+                username.0.clone().color(Color::AQUA)
+                    + " is not on your course.".color(Color::RED),
+            );
+            continue;
+        }
+
+        state.pause(player_id.0);
+        *game_mode = GameMode::Spectator;
+        set_camera(&mut client, target_id);
+
+        commands.entity(event.executor).insert(Spectating(target));
+
+        client.send_chat_message(
+            "Spectating ".color(Color::GREEN)
+                + username.0.clone().color(Color::AQUA)
+                + ". Use ".color(Color::GREEN)
+                + "/resume".on_click_run_command("/resume").color(Color::AQUA)
+                + " to get back to your run.".color(Color::GREEN),
+        );
+    }
+}
+
+/// Moves a player onto another layer, leaving the one they were on.
+fn move_to_layer(
+    layer: Entity,
+    spawn: DVec3,
+    layer_id: &mut EntityLayerId,
+    visible_chunk_layer: &mut VisibleChunkLayer,
+    visible_entity_layers: &mut VisibleEntityLayers,
+    pos: &mut Position,
+) {
+    visible_entity_layers.0.remove(&layer_id.0);
+
+    layer_id.0 = layer;
+    visible_chunk_layer.0 = layer;
+    visible_entity_layers.0.insert(layer);
+    pos.set(spawn);
+}
+
+fn handle_invite_command(
+    mut events: EventReader<CommandResultEvent<Invite>>,
+    mut clients: Query<(&mut Client, &Username, &VisibleChunkLayer)>,
+    players: Query<(Entity, &Username)>,
+    courses: Query<(&CourseInstance, &CourseOwner)>,
+    mut commands: Commands,
+    settings: Res<Settings>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    for event in events.read() {
+        let Ok((mut client, username, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+
+        if !settings.private {
+            client.send_chat_message(
+                "Everyone already plays on the same courses here.".color(Color::RED),
+            );
+            continue;
+        }
+
+        let course = match courses.get(layer.0) {
+            Ok((instance, owner)) if owner.0 == event.executor => instance.name.clone(),
+            Ok(_) => {
+                client.send_chat_message(
+                    "Only the player this copy was made for can invite others.".color(Color::RED),
+                );
+                continue;
+            }
+            Err(_) => {
+                client.send_chat_message(
+                    "Join a course to invite others onto your copy of it.".color(Color::RED),
+                );
+                continue;
+            }
+        };
+
+        let Some((target, target_name)) = players
+            .iter()
+            .find(|(_, target_name)| target_name.0.eq_ignore_ascii_case(&event.result.player))
+        else {
+            client.send_chat_message(
+                "There is no player called ".color(Color::RED)
+                    + event.result.player.clone().color(Color::AQUA)
+                    + ".".color(Color::RED),
+            );
+            continue;
+        };
+
+        if target == event.executor {
+            client.send_chat_message("You can't invite yourself.".color(Color::RED));
+            continue;
+        }
+
+        client.send_chat_message(
+            "Invited ".color(Color::GREEN) + target_name.0.clone().color(Color::AQUA) + ".",
+        );
+        let username = username.0.clone();
+
+        commands.entity(target).insert(Invitation(layer.0));
+
+        if let Ok((mut target_client, ..)) = clients.get_mut(target) {
+            target_client.send_chat_message(
+                username.color(Color::AQUA)
+                    + " invited you to their copy of ".color(Color::GREEN)
+                    + course.clone().color(Color::AQUA)
+                    + ". ".color(Color::GREEN)
+                    + format!("/join {course}")
+                        .on_click_run_command(format!("/join {course}"))
+                        .on_hover_show_text("Click to run.")
+                        .color(Color::AQUA),
+            );
+        }
+    }
+}
+
+fn handle_join_command(
+    mut events: EventReader<CommandResultEvent<Join>>,
+    mut clients: Query<(
+        &mut Client,
+        &UniqueId,
+        &mut EntityLayerId,
+        &mut VisibleChunkLayer,
+        &mut VisibleEntityLayers,
+        &mut Position,
+        Option<&Invitation>,
+    )>,
+    mut courses: Query<(Entity, &CourseInstance, &mut GameState, Has<CourseOwner>)>,
+    lobby: Query<Entity, With<Lobby>>,
+    mut commands: Commands,
+    dimensions: Res<DimensionTypeRegistry>,
+    biomes: Res<BiomeRegistry>,
+    server: Res<Server>,
+    settings: Res<Settings>,
+    terrain: Res<TerrainCache>,
+    remotes: Query<&RemoteExecutor>,
+) {
+    let lobby = lobby.single();
+
+    for event in events.read() {
+        let Ok((
+            mut client,
+            player_id,
+            mut layer_id,
+            mut visible_chunk_layer,
+            mut visible_entity_layers,
+            mut pos,
+            invitation,
+        )) = clients.get_mut(event.executor)
+        else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+
+        let Some(config) = settings.course(&event.result.course) else {
+            client.send_chat_message(
+                "There is no course called ".color(Color::RED)
+                    + event.result.course.clone().color(Color::AQUA)
+                    + ". See ".color(Color::RED)
+                    + "/courses".on_click_run_command("/courses").color(Color::AQUA)
+                    + ".".color(Color::RED),
+            );
+            continue;
+        };
+
+        // Players invited onto a copy of this course that's still around share
+        // it with whoever invited them
+        let invited = invitation.map(|invitation| invitation.0).filter(|e_layer| {
+            courses
+                .get(*e_layer)
+                .is_ok_and(|(_, instance, ..)| instance.name == config.name)
+        });
+
+        let e_layer = if let Some(e_layer) = invited {
+            e_layer
+        } else if settings.private {
+            let seed = config.seed.or(settings.seed).unwrap_or_else(rand::random);
+            let (e_layer, _) = spawn_course(
+                &mut commands,
+                &dimensions,
+                &biomes,
+                &server,
+                &settings,
+                config,
+                seed,
+                &terrain,
+            );
+            commands
+                .entity(e_layer)
+                .insert(CourseOwner(event.executor));
+
+            e_layer
+        } else {
+            let Some((e_layer, ..)) = courses
+                .iter()
+                .find(|(_, instance, _, private)| !private && instance.name == config.name)
+            else {
+                continue;
+            };
+
+            e_layer
+        };
+
+        // Leave the course the player is on now and wait in the lobby until
+        // the new one is ready
+        if let Ok((_, _, mut state, _)) = courses.get_mut(visible_chunk_layer.0) {
+            state.remove_player(&player_id.0);
+        }
+
+        if visible_chunk_layer.0 != lobby {
+            move_to_layer(
+                lobby,
+                lobby_spawn(),
+                &mut layer_id,
+                &mut visible_chunk_layer,
+                &mut visible_entity_layers,
+                &mut pos,
+            );
+        }
+
+        commands.entity(event.executor).insert(PendingJoin(e_layer));
+
+        client.send_chat_message(
+            "Joining ".color(Color::GREEN) + config.name.clone().color(Color::AQUA).bold() + "...",
+        );
+    }
+}
+
+fn move_pending_players(
+    settings: Res<Settings>,
+    mut clients: Query<(
+        Entity,
+        &mut Client,
+        &UniqueId,
+        &PendingJoin,
+        &mut EntityLayerId,
+        &mut VisibleChunkLayer,
+        &mut VisibleEntityLayers,
+        &mut Position,
+        &mut GameMode,
+    )>,
+    mut courses: Query<(&ChunkLayer, &mut GameState)>,
+    mut commands: Commands,
+) {
+    for (
+        entity,
+        mut client,
+        player_id,
+        pending,
+        mut layer_id,
+        mut visible_chunk_layer,
+        mut visible_entity_layers,
+        mut pos,
+        mut game_mode,
+    ) in &mut clients
+    {
+        // A freshly spawned private course may not exist until the next tick
+        let Ok((layer, mut state)) = courses.get_mut(pending.0) else {
+            client.send_action_bar_message("Preparing your course...".color(Color::YELLOW));
+            continue;
+        };
+
+        if !state.ready(layer) {
+            client.send_action_bar_message(
+                "Generating: ".color(Color::YELLOW)
+                    + (state.course.len() - 1).color(Color::LIGHT_PURPLE)
+                    + " platforms".color(Color::YELLOW),
+            );
+            continue;
+        }
+
+        let spawn = state.course.get_start();
+        state
+            .player_states
+            .insert(player_id.0, PlayerOnCourse::new(spawn.into()));
+
+        move_to_layer(
+            pending.0,
+            spawn,
+            &mut layer_id,
+            &mut visible_chunk_layer,
+            &mut visible_entity_layers,
+            &mut pos,
+        );
+        *game_mode = GameMode::Adventure;
+
+        commands.entity(entity).remove::<PendingJoin>();
+
+        client.send_action_bar_message(settings.messages.course_ready.clone().color(Color::GREEN));
+    }
+}
+
+fn handle_lobby_command(
+    mut events: EventReader<CommandResultEvent<LobbyCommand>>,
+    mut clients: Query<(
+        &UniqueId,
+        &mut EntityLayerId,
+        &mut VisibleChunkLayer,
+        &mut VisibleEntityLayers,
+        &mut Position,
+        &mut GameMode,
+    )>,
+    mut states: Query<&mut GameState>,
+    lobby: Query<Entity, With<Lobby>>,
+    mut commands: Commands,
+    remotes: Query<&RemoteExecutor>,
+) {
+    let lobby = lobby.single();
+
+    for event in events.read() {
+        commands.entity(event.executor).remove::<PendingJoin>();
+
+        let Ok((
+            player_id,
+            mut layer_id,
+            mut visible_chunk_layer,
+            mut visible_entity_layers,
+            mut pos,
+            mut game_mode,
+        )) = clients.get_mut(event.executor)
+        else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
+
+        if let Ok(mut state) = states.get_mut(visible_chunk_layer.0) {
+            state.remove_player(&player_id.0);
+        }
+
+        move_to_layer(
+            lobby,
+            lobby_spawn(),
+            &mut layer_id,
+            &mut visible_chunk_layer,
+            &mut visible_entity_layers,
+            &mut pos,
+        );
+        *game_mode = GameMode::Adventure;
+    }
+}
+
 fn handle_gamemode_command(
     mut events: EventReader<CommandResultEvent<GamemodeCommand>>,
-    mut clients: Query<(&mut GameMode, &UniqueId, Entity)>,
-    mut state: Query<&mut GameState>,
+    mut clients: Query<(&mut GameMode, &UniqueId, &VisibleChunkLayer)>,
+    mut states: Query<&mut GameState>,
+    remotes: Query<&RemoteExecutor>,
 ) {
-    let mut state = state.single_mut();
-
     for event in events.read() {
-        let (mut gamemode, player_id, ..) = clients.get_mut(event.executor).unwrap();
+        let Ok((mut gamemode, player_id, layer)) = clients.get_mut(event.executor) else {
+            players_only(&remotes, event.executor);
+            continue;
+        };
 
         let game_mode_to_set = match &event.result {
             GamemodeCommand::Creative => GameMode::Creative,
@@ -345,87 +2285,762 @@ fn handle_gamemode_command(
             GamemodeCommand::Spectator => GameMode::Spectator,
         };
 
-        match &event.result {
-            GamemodeCommand::Adventure => (),
-            _ => state.pause(player_id.0),
+        if let Ok(mut state) = states.get_mut(layer.0) {
+            match &event.result {
+                GamemodeCommand::Adventure => (),
+                _ => state.pause(player_id.0),
+            }
         }
 
         *gamemode = game_mode_to_set;
     }
 }
 
-pub fn main() {
-    let cli = Cli::parse();
+/// Finds an online player by name, for commands that need their UUID.
+fn find_player(
+    players: &Query<(Entity, &UniqueId, &Username)>,
+    name: &str,
+) -> Option<(Entity, Uuid, String)> {
+    players
+        .iter()
+        .find(|(_, _, username)| username.0.eq_ignore_ascii_case(name))
+        .map(|(entity, player_id, username)| (entity, player_id.0, username.0.clone()))
+}
+
+/// Told whenever the whitelist is switched by command.
+const TEMPORARY_WHITELIST: &str =
+    "Set `server.whitelist` in the config file to keep it that way after a restart.";
 
-    if !cli.path.exists() {
-        eprintln!(
-            "Directory `{}` does not exist. Exiting.",
-            cli.path.display()
+fn not_online(name: &str) -> Text {
+    name.to_owned().color(Color::AQUA) + " isn't online.".color(Color::RED)
+}
+
+fn handle_whitelist_command(
+    mut events: EventReader<CommandResultEvent<WhitelistCommand>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    players: Query<(Entity, &UniqueId, &Username)>,
+    access: Res<AccessLists>,
+) {
+    for event in events.read() {
+        let message = match &event.result {
+            WhitelistCommand::Add { player } => {
+                // Players who aren't online are matched by name when they join
+                let (player_id, name) = match find_player(&players, player) {
+                    Some((_, player_id, name)) => (Some(player_id), name),
+                    None => (None, player.clone()),
+                };
+
+                if access.whitelist(player_id, &name) {
+                    "Added ".color(Color::GREEN) + name.color(Color::AQUA) + " to the whitelist."
+                } else {
+                    name.color(Color::AQUA) + " is already whitelisted.".color(Color::RED)
+                }
+            }
+            WhitelistCommand::Remove { player } => {
+                if access.unwhitelist(player) {
+                    "Removed ".color(Color::GREEN)
+                        + player.clone().color(Color::AQUA)
+                        + " from the whitelist."
+                } else {
+                    player.clone().color(Color::AQUA) + " isn't whitelisted.".color(Color::RED)
+                }
+            }
+            // The config file has the final say, so this only lasts until it
+            // changes or the server restarts
+            WhitelistCommand::On => {
+                access.set_whitelist_enabled(true);
+                "The whitelist is on. ".color(Color::GREEN) + TEMPORARY_WHITELIST.color(Color::GRAY)
+            }
+            WhitelistCommand::Off => {
+                access.set_whitelist_enabled(false);
+                "The whitelist is off. ".color(Color::GREEN)
+                    + TEMPORARY_WHITELIST.color(Color::GRAY)
+            }
+            WhitelistCommand::List => {
+                let names = access.whitelisted();
+
+                if names.is_empty() {
+                    "Nobody is whitelisted.".color(Color::YELLOW)
+                } else {
+                    "Whitelisted: ".color(Color::YELLOW) + names.join(", ").color(Color::AQUA)
+                }
+            }
+        };
+
+        reply(&mut clients, &remotes, event.executor, message);
+    }
+}
+
+fn handle_ban_command(
+    mut events: EventReader<CommandResultEvent<Ban>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    players: Query<(Entity, &UniqueId, &Username)>,
+    access: Res<AccessLists>,
+    mut commands: Commands,
+) {
+    for event in events.read() {
+        let source = players
+            .get(event.executor)
+            .map_or_else(|_| "Server".to_owned(), |(_, _, username)| username.0.clone());
+
+        // Players who aren't online are matched by name when they try to join
+        let (target, player_id, name) = match find_player(&players, &event.result.player) {
+            Some((target, player_id, name)) => (Some(target), Some(player_id), name),
+            None => (None, None, event.result.player.clone()),
+        };
+
+        let reason = event
+            .result
+            .reason
+            .as_ref()
+            .map_or("Banned by an operator.", |reason| reason.0.as_str());
+        access.ban(player_id, &name, reason, &source);
+        println!("{source} banned {name}: {reason}");
+
+        if let Some(target) = target {
+            if let Ok(mut client) = clients.get_mut(target) {
+                kick(
+                    &mut commands,
+                    target,
+                    &mut client,
+                    "You are banned from this server.\nReason: ".color(Color::RED)
+                        + reason.to_owned(),
+                );
+            }
+        }
+
+        reply(
+            &mut clients,
+            &remotes,
+            event.executor,
+            "Banned ".color(Color::GREEN) + name.color(Color::AQUA) + ".",
         );
-        return;
     }
+}
 
-    if !cli.path.is_dir() {
-        eprintln!("`{}` is not a directory. Exiting.", cli.path.display());
+fn handle_pardon_command(
+    mut events: EventReader<CommandResultEvent<Pardon>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    access: Res<AccessLists>,
+) {
+    for event in events.read() {
+        let player = &event.result.player;
+
+        let message = if access.pardon(player) {
+            "Pardoned ".color(Color::GREEN) + player.clone().color(Color::AQUA) + "."
+        } else {
+            player.clone().color(Color::AQUA) + " isn't banned.".color(Color::RED)
+        };
+
+        reply(&mut clients, &remotes, event.executor, message);
+    }
+}
+
+fn handle_op_command(
+    mut events: EventReader<CommandResultEvent<Op>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    mut scopes: Query<&mut CommandScopes>,
+    players: Query<(Entity, &UniqueId, &Username)>,
+    access: Res<AccessLists>,
+) {
+    for event in events.read() {
+        let message = match find_player(&players, &event.result.player) {
+            Some((target, player_id, name)) if access.op(player_id, &name) => {
+                if let Ok(mut target_scopes) = scopes.get_mut(target) {
+                    target_scopes.add(&group_scope(OPERATOR_GROUP));
+                }
+                if let Ok(mut client) = clients.get_mut(target) {
+                    client.send_chat_message("You are now an operator.".color(Color::GREEN));
+                }
+
+                "Made ".color(Color::GREEN) + name.color(Color::AQUA) + " an operator."
+            }
+            Some((_, _, name)) => {
+                name.color(Color::AQUA) + " already is an operator.".color(Color::RED)
+            }
+            None => not_online(&event.result.player),
+        };
+
+        reply(&mut clients, &remotes, event.executor, message);
+    }
+}
+
+fn handle_deop_command(
+    mut events: EventReader<CommandResultEvent<Deop>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    mut players: Query<(Entity, &UniqueId, &mut CommandScopes)>,
+    access: Res<AccessLists>,
+) {
+    for event in events.read() {
+        let player = &event.result.player;
+
+        let message = match access.deop(player) {
+            Some(player_id) => {
+                if let Some((target, _, mut scopes)) =
+                    players.iter_mut().find(|(_, id, _)| id.0 == player_id)
+                {
+                    scopes.remove(&group_scope(OPERATOR_GROUP));
+
+                    if let Ok(mut client) = clients.get_mut(target) {
+                        client.send_chat_message(
+                            "You are no longer an operator.".color(Color::YELLOW),
+                        );
+                    }
+                }
+
+                player.clone().color(Color::AQUA) + " is no longer an operator.".color(Color::GREEN)
+            }
+            None => player.clone().color(Color::AQUA) + " isn't an operator.".color(Color::RED),
+        };
+
+        reply(&mut clients, &remotes, event.executor, message);
+    }
+}
+
+fn handle_kick_command(
+    mut events: EventReader<CommandResultEvent<Kick>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    players: Query<(Entity, &UniqueId, &Username)>,
+    mut commands: Commands,
+) {
+    for event in events.read() {
+        let Some((target, _, name)) = find_player(&players, &event.result.player) else {
+            reply(&mut clients, &remotes, event.executor, not_online(&event.result.player));
+            continue;
+        };
+
+        let reason = event
+            .result
+            .reason
+            .as_ref()
+            .map_or("Kicked by an operator.", |reason| reason.0.as_str());
+
+        if let Ok(mut client) = clients.get_mut(target) {
+            kick(&mut commands, target, &mut client, reason.to_owned().color(Color::RED));
+        }
+
+        reply(
+            &mut clients,
+            &remotes,
+            event.executor,
+            "Kicked ".color(Color::GREEN) + name.color(Color::AQUA) + ".",
+        );
+    }
+}
+
+fn handle_list_command(
+    mut events: EventReader<CommandResultEvent<List>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    players: Query<(&Username, &UniqueId, &VisibleChunkLayer)>,
+    courses: Query<(&CourseInstance, &GameState)>,
+) {
+    for event in events.read() {
+        let mut list = format!("Players online ({}):", players.iter().count())
+            .color(Color::YELLOW)
+            .bold();
+
+        for (username, player_id, layer) in &players {
+            let whereabouts = match courses.get(layer.0) {
+                Ok((instance, state)) => format!(
+                    " - platform {} on {}",
+                    state.current_platform(&player_id.0),
+                    instance.name
+                ),
+                Err(_) => " - in the lobby".to_owned(),
+            };
+
+            list = list
+                + "\n".not_bold()
+                + username.0.clone().color(Color::AQUA).not_bold()
+                + whereabouts.color(Color::WHITE).not_bold();
+        }
+
+        reply(&mut clients, &remotes, event.executor, list);
+    }
+}
+
+fn handle_regenerate_command(
+    mut events: EventReader<CommandResultEvent<Regenerate>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    mut players: Query<(&UniqueId, &mut Position, &mut GameMode, &VisibleChunkLayer)>,
+    mut courses: Query<(Entity, &CourseInstance, &mut ChunkLayer, &mut GameState)>,
+) {
+    for event in events.read() {
+        let mut regenerated = 0;
+
+        for (entity, instance, mut layer, mut state) in &mut courses {
+            if event
+                .result
+                .course
+                .as_ref()
+                .is_some_and(|course| *course != instance.name)
+            {
+                continue;
+            }
+
+            regenerate_course(entity, &mut state, &mut layer, &mut players);
+            regenerated += 1;
+        }
+
+        let message = match &event.result.course {
+            Some(course) if regenerated == 0 => {
+                "There is no course called ".color(Color::RED)
+                    + course.clone().color(Color::AQUA)
+                    + ".".color(Color::RED)
+            }
+            _ => format!("Regenerated {regenerated} courses.").color(Color::GREEN),
+        };
+
+        reply(&mut clients, &remotes, event.executor, message);
+    }
+}
+
+fn handle_seed_command(
+    mut events: EventReader<CommandResultEvent<Seed>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    courses: Query<(&CourseInstance, &GameState)>,
+) {
+    for event in events.read() {
+        let mut list = "Seeds:".color(Color::YELLOW).bold();
+
+        for (instance, state) in &courses {
+            list = list
+                + "\n".not_bold()
+                + instance.name.clone().color(Color::AQUA).not_bold()
+                + format!(": {}", state.course.seed())
+                    .color(Color::WHITE)
+                    .not_bold();
+        }
+
+        reply(&mut clients, &remotes, event.executor, list);
+    }
+}
+
+fn handle_reseed_command(
+    mut events: EventReader<CommandResultEvent<Reseed>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    mut players: Query<(&UniqueId, &mut Position, &mut GameMode, &VisibleChunkLayer)>,
+    mut courses: Query<(Entity, &CourseInstance, &mut ChunkLayer, &mut GameState)>,
+) {
+    for event in events.read() {
+        let Ok(seed) = event.result.seed.parse() else {
+            reply(
+                &mut clients,
+                &remotes,
+                event.executor,
+                format!("{} is not a valid seed.", event.result.seed).color(Color::RED),
+            );
+            continue;
+        };
+
+        let mut reseeded = false;
+        for (entity, instance, mut layer, mut state) in &mut courses {
+            if instance.name != event.result.course {
+                continue;
+            }
+
+            // The heatmap of the old course is exported under the old seed
+            regenerate_course(entity, &mut state, &mut layer, &mut players);
+            state.course.set_seed(seed);
+            reseeded = true;
+        }
+
+        let message = if reseeded {
+            "Regenerated ".color(Color::GREEN)
+                + event.result.course.clone().color(Color::AQUA)
+                + format!(" from seed {seed}.").color(Color::GREEN)
+        } else {
+            "There is no course called ".color(Color::RED)
+                + event.result.course.clone().color(Color::AQUA)
+                + ".".color(Color::RED)
+        };
+
+        reply(&mut clients, &remotes, event.executor, message);
+    }
+}
+
+/// Sends everyone on the course back to the start of a freshly generated one.
+fn regenerate_course(
+    entity: Entity,
+    state: &mut GameState,
+    layer: &mut ChunkLayer,
+    players: &mut Query<(&UniqueId, &mut Position, &mut GameMode, &VisibleChunkLayer)>,
+) {
+    // Rounds can't go on without the course they're played on
+    state.race = None;
+    state.elimination = None;
+    state.relays.clear();
+
+    for (player_id, mut pos, mut game_mode, player_layer) in players {
+        if player_layer.0 == entity {
+            state.reset_player(player_id.0, pos.as_mut());
+            *game_mode = GameMode::Adventure;
+        }
+    }
+
+    state.reset_course(layer);
+}
+
+/// Writes everything that isn't written right away, returning how many courses
+/// couldn't be saved.
+fn save_courses(courses: &Query<&GameState>) -> usize {
+    courses
+        .iter()
+        .filter(|state| match state.export_heatmap() {
+            Ok(()) => false,
+            Err(e) => {
+                eprintln!("failed to export the jump heatmap: {e:#}");
+                true
+            }
+        })
+        .count()
+}
+
+fn handle_save_command(
+    mut events: EventReader<CommandResultEvent<Save>>,
+    mut clients: Query<&mut Client>,
+    remotes: Query<&RemoteExecutor>,
+    courses: Query<&GameState>,
+) {
+    for event in events.read() {
+        let message = match save_courses(&courses) {
+            0 => "Saved.".color(Color::GREEN),
+            failed => format!("Failed to save {failed} courses.").color(Color::RED),
+        };
+
+        reply(&mut clients, &remotes, event.executor, message);
+    }
+}
+
+fn handle_stop_command(
+    mut events: EventReader<CommandResultEvent<Stop>>,
+    mut clients: Query<(Entity, &mut Client)>,
+    courses: Query<&GameState>,
+    mut exit: EventWriter<AppExit>,
+    mut commands: Commands,
+) {
+    if events.is_empty() {
         return;
     }
+    events.clear();
+
+    println!("Stopping the server");
+    save_courses(&courses);
+
+    for (entity, mut client) in &mut clients {
+        kick(
+            &mut commands,
+            entity,
+            &mut client,
+            "The server is shutting down.".color(Color::YELLOW),
+        );
+    }
+
+    exit.send(AppExit::Success);
+}
+
+pub fn main() {
+    let cli = Cli::parse();
+    let settings = match Settings::load(&cli) {
+        Ok(settings) => settings,
+        Err(e) => {
+            eprintln!("{e} Exiting.");
+            return;
+        }
+    };
+
+    let access = match AccessLists::load(settings.whitelist) {
+        Ok(access) => access,
+        Err(e) => {
+            eprintln!("{e} Exiting.");
+            return;
+        }
+    };
+
+    let permissions = match Permissions::load(Path::new(PERMISSIONS_PATH)) {
+        Ok(permissions) => permissions,
+        Err(e) => {
+            eprintln!("{e} Exiting.");
+            return;
+        }
+    };
+
+    let status = ServerStatus::default();
 
     App::new()
         .insert_resource(NetworkSettings {
-            callbacks: ServerListPingCallback.into(),
+            address: settings.address,
+            max_players: settings.max_players + settings.queue_size,
+            connection_mode: settings.connection_mode.clone(),
+            callbacks: ServerCallbacks {
+                status: status.clone(),
+                access: access.clone(),
+                max_players: settings.max_players,
+                queue_size: settings.queue_size,
+                motd: settings.motd.clone(),
+                favicon: settings.favicon.clone(),
+            }
+            .into(),
             ..Default::default()
         })
         .add_plugins(DefaultPlugins)
         .add_command::<Help>()
         .add_command::<Info>()
+        .add_command::<Back>()
+        .add_command::<Practice>()
+        .add_command::<Teleport>()
+        .add_command::<SetCheckpoint>()
+        .add_command::<Spectate>()
+        .add_command::<Courses>()
+        .add_command::<Join>()
+        .add_command::<Invite>()
+        .add_command::<LobbyCommand>()
         .add_command::<Reset>()
         .add_command::<Kill>()
         .add_command::<Pause>()
         .add_command::<Resume>()
+        .add_command::<RaceCommand>()
+        .add_command::<EliminationCommand>()
+        .add_command::<TeamCommand>()
+        .add_command::<RelayCommand>()
+        .add_command::<JumpStatsCommand>()
         .add_command::<GamemodeCommand>()
-        .insert_resource(cli)
-        .add_systems(Startup, setup)
+        .add_command::<WhitelistCommand>()
+        .add_command::<Ban>()
+        .add_command::<Pardon>()
+        .add_command::<Op>()
+        .add_command::<Deop>()
+        .add_command::<Kick>()
+        .add_command::<List>()
+        .add_command::<Regenerate>()
+        .add_command::<Seed>()
+        .add_command::<Reseed>()
+        .add_command::<Save>()
+        .add_command::<Stop>()
+        .insert_resource(Records::load(Path::new(RECORDS_PATH)))
+        .insert_resource(status)
+        .insert_resource(JoinQueue::default())
+        .insert_resource(TerrainCache::default())
+        .insert_resource(RemoteCommands::default())
+        .insert_resource(access)
+        .insert_resource(permissions)
+        .insert_resource(settings)
+        .insert_resource(ConfigWatcher::new(cli))
+        .add_systems(
+            Startup,
+            (setup, ask_server_for_platforms, spawn_console, start_rcon, start_http),
+        )
+        .add_systems(First, disconnect_kicked_players)
         .add_systems(
             Update,
             (
                 despawn_disconnected_clients,
+                despawn_abandoned_courses,
+                update_server_status,
                 override_display_name_to_platform_no,
-                manage_course,
-                (init_clients, handle_chunk_loads).chain(),
+                (
+                    manage_course,
+                    run_races,
+                    run_eliminations,
+                    run_relays,
+                    (update_sidebars, update_progress_bars, update_spectators),
+                )
+                    .chain(),
+                (init_clients, admit_queued_players, handle_chunk_loads).chain(),
+                reload_config,
+                read_console,
+                run_remote_commands,
+            ),
+        )
+        .add_systems(
+            Update,
+            (
                 handle_help_command,
                 handle_info_command,
+                handle_courses_command,
+                (handle_invite_command, handle_join_command, move_pending_players).chain(),
+                handle_lobby_command,
                 handle_reset_command,
                 handle_kill_command,
-                handle_pause_command,
-                handle_resume_command,
+                (handle_back_command, handle_pause_command, handle_resume_command),
+                handle_practice_command,
+                handle_teleport_command,
+                handle_setcheckpoint_command,
+                handle_completion_requests,
+                handle_spectate_command,
+                handle_race_command,
+                handle_elimination_command,
+                handle_team_command,
+                handle_relay_command,
+                handle_jumpstats_command,
                 handle_gamemode_command,
+                (
+                    handle_whitelist_command,
+                    handle_ban_command,
+                    handle_pardon_command,
+                    handle_op_command,
+                    handle_deop_command,
+                    handle_kick_command,
+                    handle_list_command,
+                    handle_regenerate_command,
+                    handle_seed_command,
+                    handle_reseed_command,
+                    handle_save_command,
+                    handle_stop_command,
+                ),
             ),
         )
         .run();
 }
 
-struct ServerListPingCallback;
+struct ServerCallbacks {
+    status: ServerStatus,
+    access: AccessLists,
+    max_players: usize,
+    queue_size: usize,
+    motd: String,
+    favicon: Vec<u8>,
+}
+
+#[async_trait]
+impl NetworkCallbacks for ServerCallbacks {
+    async fn server_list_ping(
+        &self,
+        shared: &SharedNetworkState,
+        _remote_addr: SocketAddr,
+        _handshake_data: &HandshakeData,
+    ) -> ServerListPing {
+        let player_sample = self
+            .status
+            .top_players(PLAYER_SAMPLE_SIZE)
+            .into_iter()
+            .map(|player| PlayerSampleEntry {
+                name: format!(
+                    "{} - platform {} on {}",
+                    player.name, player.platform, player.course
+                ),
+                id: player.id,
+            })
+            .collect();
+
+        ServerListPing::Respond {
+            online_players: shared.player_count().load(Ordering::Relaxed) as i32,
+            max_players: self.max_players as i32,
+            player_sample,
+            description: self.status.instances().iter().enumerate().fold(
+                format!("{}\n", self.motd).into_text(),
+                |description, (i, instance)| {
+                    let separator = if i == 0 { "" } else { " | " };
+                    let generating = if instance.generating {
+                        " · generating".color(Color::YELLOW)
+                    } else {
+                        "".into_text()
+                    };
+
+                    description
+                        + separator.color(Color::GRAY)
+                        + instance.name.clone().color(Color::AQUA)
+                        + format!(" · {} platforms", instance.platforms).color(Color::LIGHT_PURPLE)
+                        + generating
+                },
+            ),
+            favicon_png: &self.favicon,
+            version_name: ("Valence ".color(Color::GOLD) + MINECRAFT_VERSION.color(Color::RED))
+                .to_legacy_lossy(),
+            protocol: PROTOCOL_VERSION,
+        }
+    }
+
+    async fn login(&self, shared: &SharedNetworkState, info: &NewClientInfo) -> Result<(), Text> {
+        if let Err(reason) = self.access.check_login(info.uuid, &info.username) {
+            return Err(reason.color(Color::RED));
+        }
+
+        let online = shared.player_count().load(Ordering::Relaxed);
+
+        if online >= self.max_players + self.queue_size {
+            let full = if self.queue_size > 0 {
+                "The server and its queue are full"
+            } else {
+                "The server is full"
+            };
+
+            let reason = format!("{full} ({} players), please try again later.", self.max_players);
+            return Err(reason.color(Color::YELLOW));
+        }
+
+        Ok(())
+    }
+}
+
+/// Spawns a course layer with its own Anvil level and generator, returning the
+/// layer entity and the position players start at. The terrain around the
+/// spawn comes from the cache once another course loaded it.
+fn spawn_course(
+    commands: &mut Commands,
+    dimensions: &DimensionTypeRegistry,
+    biomes: &BiomeRegistry,
+    server: &Server,
+    settings: &Settings,
+    config: &CourseConfig,
+    seed: u64,
+    terrain: &TerrainCache,
+) -> (Entity, DVec3) {
+    let mut layer = LayerBundle::new(ident!("overworld"), dimensions, biomes, server);
+    let mut level = AnvilLevel::new(&settings.world, biomes);
 
-#[async_trait]
-impl NetworkCallbacks for ServerListPingCallback {
-    async fn server_list_ping(
-        &self,
-        _shared: &SharedNetworkState,
-        _remote_addr: SocketAddr,
-        _handshake_data: &HandshakeData,
-    ) -> ServerListPing {
-        ServerListPing::Respond {
-            // TODO: Implement a real player count
-            online_players: 0,
-            max_players: 1,
-            player_sample: vec![],
-            description: "A tini parkour server".into_text(),
-            favicon_png: include_bytes!("logo.png"),
-            version_name: ("Valence ".color(Color::GOLD) + MINECRAFT_VERSION.color(Color::RED))
-                .to_legacy_lossy(),
-            protocol: PROTOCOL_VERSION,
+    let radius = settings.preload_radius;
+    if !terrain.fill(&mut layer.chunk, &mut level, radius) {
+        for pos in TerrainCache::preload_area(radius) {
+            level.ignored_chunks.insert(pos);
+            level.force_chunk_load(pos);
         }
     }
+
+    let mut course = ParkourCourse::new(
+        settings.spawn,
+        &layer.chunk,
+        seed,
+        config.preset,
+        config.length,
+        settings.palettes.clone(),
+    );
+    course.set_jump_table(settings.jump_table.clone());
+    let start = course.get_start();
+
+    let entity = commands
+        .spawn((
+            layer,
+            level,
+            GameState {
+                name: config.name.clone(),
+                course,
+                player_states: HashMap::new(),
+                race: None,
+                elimination: None,
+                relays: Vec::new(),
+                teams: Teams::default(),
+                adaptive: settings.adaptive,
+                stats_changed: false,
+                skip_policy: settings.skip_policy,
+            },
+            CourseInstance {
+                name: config.name.clone(),
+            },
+        ))
+        .id();
+
+    (entity, start)
 }
 
 fn setup(
@@ -434,109 +3049,412 @@ fn setup(
     biomes: Res<BiomeRegistry>,
     server: Res<Server>,
     mut command_scopes: ResMut<CommandScopeRegistry>,
-    cli: Res<Cli>,
+    permissions: Res<Permissions>,
+    settings: Res<Settings>,
+    terrain: Res<TerrainCache>,
 ) {
-    let layer = LayerBundle::new(ident!("overworld"), &dimensions, &biomes, &server);
-    let mut level = AnvilLevel::new(&cli.path, &biomes);
+    permissions.register(&mut command_scopes);
 
-    for z in -16..16 {
-        for x in -16..16 {
-            let pos = ChunkPos::new(x, z);
+    spawn_lobby(&mut commands, &dimensions, &biomes, &server);
+    spawn_waiting_room(&mut commands, &dimensions, &biomes, &server);
 
-            level.ignored_chunks.insert(pos);
-            level.force_chunk_load(pos);
+    // Private courses are only spawned once someone joins them
+    if !settings.private {
+        for config in &settings.courses {
+            let seed = config.seed.or(settings.seed).unwrap_or_else(rand::random);
+
+            spawn_course(
+                &mut commands,
+                &dimensions,
+                &biomes,
+                &server,
+                &settings,
+                config,
+                seed,
+                &terrain,
+            );
         }
     }
+}
 
-    let position = cli
-        .spawn
-        .split(',')
-        .map(|s| s.trim().parse().ok())
-        .collect::<Vec<Option<i32>>>();
-    let position = BlockPos::new(
-        position[0].unwrap_or(0),
-        position[1].unwrap_or(196),
-        position[2].unwrap_or(0),
-    );
+/// Reloads the config file when it changes. Settings that only matter for
+/// newly generated platforms are applied right away, the others are reported
+/// to admins.
+fn reload_config(
+    mut watcher: ResMut<ConfigWatcher>,
+    mut settings: ResMut<Settings>,
+    mut courses: Query<&mut GameState>,
+    mut clients: Query<(&mut Client, &CommandScopes)>,
+    scopes: Res<CommandScopeRegistry>,
+    access: Res<AccessLists>,
+) {
+    if !watcher.changed() {
+        return;
+    }
 
-    let course = ParkourCourse::new(position, &layer.chunk);
+    let new = match watcher.reload() {
+        Ok(new) => new,
+        Err(e) => {
+            eprintln!("Failed to reload config: {e}");
+            return;
+        }
+    };
 
-    command_scopes.link("parkour.actor", "parkour.command");
+    // Only touch the whitelist if the file changed it, to keep `/whitelist on`
+    if new.whitelist != settings.whitelist {
+        access.set_whitelist_enabled(new.whitelist);
+    }
 
-    commands.spawn((
-        layer,
-        level,
-        GameState {
-            course,
-            player_states: HashMap::new(),
-        },
-    ));
+    let need_restart = settings.apply(new);
+    let need_restart = watcher.unreported(need_restart);
+
+    for mut state in &mut courses {
+        state.course.set_palettes(settings.palettes.clone());
+        state.course.set_jump_table(settings.jump_table.clone());
+        state.adaptive = settings.adaptive;
+        state.skip_policy = settings.skip_policy;
+    }
+
+    println!("Reloaded config");
+
+    if need_restart.is_empty() {
+        return;
+    }
+
+    let note = format!("Changes to {} need a restart.", need_restart.join(", "));
+    println!("{note}");
+
+    for (mut client, client_scopes) in &mut clients {
+        if scopes.any_grants(&client_scopes.0, ADMIN_SCOPE) {
+            client.send_chat_message("[Config] ".color(Color::GOLD) + note.clone());
+        }
+    }
 }
 
 fn init_clients(
     mut clients: Query<
         (
-            &mut UniqueId,
+            &mut Client,
             &mut EntityLayerId,
             &mut VisibleChunkLayer,
             &mut VisibleEntityLayers,
             &mut Position,
             &mut GameMode,
             &mut CommandScopes,
+            &UniqueId,
+            Entity,
         ),
         Added<Client>,
     >,
-    layers: Query<Entity, With<ChunkLayer>>,
-    mut course: Query<&mut GameState>,
+    all_clients: Query<(), With<Client>>,
+    lobby: Query<Entity, With<Lobby>>,
+    waiting_room: Query<Entity, With<WaitingRoom>>,
+    mut queue: ResMut<JoinQueue>,
+    mut commands: Commands,
+    permissions: Res<Permissions>,
+    access: Res<AccessLists>,
+    status: Res<ServerStatus>,
+    settings: Res<Settings>,
 ) {
-    let mut course = course.single_mut();
+    // Players who joined this tick only count once they're let in
+    let mut playing = all_clients
+        .iter()
+        .count()
+        .saturating_sub(queue.len() + clients.iter().count());
 
     for (
-        player_uuid,
+        mut client,
         mut layer_id,
         mut visible_chunk_layer,
         mut visible_entity_layers,
         mut pos,
         mut game_mode,
         mut command_scope,
+        player_id,
+        entity,
     ) in &mut clients
     {
-        let e_layer = layers.single();
-
-        let spawn = course.course.get_start();
+        // Nobody gets ahead of the players already waiting for a slot
+        let full = playing >= settings.max_players || !queue.is_empty();
+        let e_layer = if full {
+            waiting_room.single()
+        } else {
+            lobby.single()
+        };
 
         layer_id.0 = e_layer;
         visible_chunk_layer.0 = e_layer;
         visible_entity_layers.0.insert(e_layer);
-        pos.set(spawn);
+        pos.set(lobby_spawn());
         *game_mode = GameMode::Adventure;
 
-        command_scope.add("parkour.actor");
+        commands
+            .entity(entity)
+            .insert((Sidebar::default(), ProgressBar::default()));
 
-        // Set the game state
-        course
-            .player_states
-            .entry(player_uuid.0)
-            .or_insert_with(|| PlayerOnCourse::new(spawn.into()));
+        if full {
+            queue.push(entity);
+            client.send_chat_message(
+                "The server is full, you'll be let in once a slot frees up.".color(Color::YELLOW),
+            );
+            continue;
+        }
+
+        playing += 1;
+        grant_scopes(&mut command_scope, player_id.0, &permissions, &access);
+
+        client.send_chat_message(
+            format!("{}\n", settings.messages.welcome)
+                .color(Color::YELLOW)
+                .bold()
+                + course_list(&status.instances()),
+        );
+    }
+}
+
+/// Gives a player the scopes of their permission group, and of the operator
+/// group if they're on the op list.
+fn grant_scopes(
+    scopes: &mut CommandScopes,
+    player_id: Uuid,
+    permissions: &Permissions,
+    access: &AccessLists,
+) {
+    scopes.add(&group_scope(permissions.group_of(player_id)));
+
+    if access.is_op(player_id) {
+        scopes.add(&group_scope(OPERATOR_GROUP));
+    }
+}
+
+/// Lets queued players in, in the order they joined, whenever a slot frees up.
+fn admit_queued_players(
+    mut clients: Query<(
+        &mut Client,
+        &mut EntityLayerId,
+        &mut VisibleChunkLayer,
+        &mut VisibleEntityLayers,
+        &mut Position,
+        &mut CommandScopes,
+        &UniqueId,
+    )>,
+    lobby: Query<Entity, With<Lobby>>,
+    mut queue: ResMut<JoinQueue>,
+    permissions: Res<Permissions>,
+    access: Res<AccessLists>,
+    status: Res<ServerStatus>,
+    settings: Res<Settings>,
+) {
+    queue.retain(|player| clients.contains(*player));
+    if queue.is_empty() {
+        return;
+    }
+
+    let mut playing = clients.iter().count().saturating_sub(queue.len());
+
+    while playing < settings.max_players {
+        let Some(player) = queue.pop() else {
+            break;
+        };
+        let Ok((
+            mut client,
+            mut layer_id,
+            mut visible_chunk_layer,
+            mut visible_entity_layers,
+            mut pos,
+            mut command_scope,
+            player_id,
+        )) = clients.get_mut(player)
+        else {
+            continue;
+        };
+
+        move_to_layer(
+            lobby.single(),
+            lobby_spawn(),
+            &mut layer_id,
+            &mut visible_chunk_layer,
+            &mut visible_entity_layers,
+            &mut pos,
+        );
+        grant_scopes(&mut command_scope, player_id.0, &permissions, &access);
+        playing += 1;
+
+        client.send_chat_message(
+            "A slot freed up, welcome!\n".color(Color::GREEN).bold()
+                + course_list(&status.instances()),
+        );
+    }
+
+    for (place, player) in queue.iter().enumerate() {
+        if let Ok((mut client, ..)) = clients.get_mut(player) {
+            client.send_action_bar_message(
+                "Place in queue: ".color(Color::YELLOW) + (place + 1).color(Color::AQUA),
+            );
+        }
+    }
+}
+
+fn despawn_abandoned_courses(
+    mut commands: Commands,
+    courses: Query<Entity, With<CourseOwner>>,
+    clients: Query<(&VisibleChunkLayer, Option<&PendingJoin>), With<Client>>,
+) {
+    for entity in &courses {
+        let in_use = clients.iter().any(|(layer, pending)| {
+            layer.0 == entity || pending.is_some_and(|pending| pending.0 == entity)
+        });
+
+        // Everyone on it left the server or went back to the lobby
+        if !in_use {
+            commands.entity(entity).insert(Despawned);
+        }
+    }
+}
+
+fn update_server_status(
+    courses: Query<(Entity, &CourseInstance, &GameState)>,
+    clients: Query<(&VisibleChunkLayer, &UniqueId, &Username), With<Client>>,
+    settings: Res<Settings>,
+    records: Res<Records>,
+    status: Res<ServerStatus>,
+) {
+    let instances = settings
+        .courses
+        .iter()
+        .map(|config| {
+            let copies = courses
+                .iter()
+                .filter(|(_, instance, _)| instance.name == config.name)
+                .collect::<Vec<_>>();
+
+            InstanceStatus {
+                difficulty: config.preset.name(),
+                players: clients
+                    .iter()
+                    .filter(|(layer, ..)| copies.iter().any(|(entity, ..)| *entity == layer.0))
+                    .count(),
+                platforms: copies
+                    .iter()
+                    .map(|(_, _, state)| state.course.len() - 1)
+                    .max()
+                    .unwrap_or(0),
+                generating: copies.iter().any(|(_, _, state)| !state.done()),
+                name: config.name.clone(),
+            }
+        })
+        .collect();
+
+    let players = clients
+        .iter()
+        .filter_map(|(layer, player_id, username)| {
+            let (_, instance, state) = courses.get(layer.0).ok()?;
+            let player_state = state.player_states.get(&player_id.0)?;
+
+            Some(PlayerStatus {
+                name: username.0.clone(),
+                id: player_id.0,
+                course: instance.name.clone(),
+                platform: player_state.current_platform(),
+            })
+        })
+        .collect();
+
+    let generation = courses
+        .iter()
+        .map(|(_, instance, state)| {
+            let stats = state.course.stats();
+
+            GenerationStatus {
+                course: instance.name.clone(),
+                seed: state.course.seed(),
+                platforms: state.course.len() - 1,
+                generating: !state.done(),
+                attempts: stats.attempts,
+                fails: stats.fails,
+            }
+        })
+        .collect();
+
+    status.set(instances, players, generation);
+
+    if records.is_changed() {
+        let leaderboard = |course: Option<&str>| {
+            records
+                .leaderboard(course, LEADERBOARD_SIZE)
+                .into_iter()
+                .map(|record| RunStatus {
+                    name: record.username.clone(),
+                    id: record.player,
+                    relay: record.team.is_some(),
+                    time_ms: record.time.as_millis() as u64,
+                    falls: record.falls,
+                    platforms: record.platforms,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        status.set_leaderboard(None, leaderboard(None));
+        for config in &settings.courses {
+            status.set_leaderboard(Some(&config.name), leaderboard(Some(&config.name)));
+        }
     }
 }
 
 fn manage_course(
-    mut clients: Query<(&mut Client, &mut Position, &mut Look, &UniqueId)>,
-    mut layers: Query<&mut ChunkLayer, With<AnvilLevel>>,
-    mut courses: Query<&mut GameState>,
+    mut clients: Query<(
+        &mut Client,
+        &mut Position,
+        &mut Look,
+        &UniqueId,
+        &Username,
+        &VisibleChunkLayer,
+        Has<Spectating>,
+    )>,
+    mut courses: Query<(Entity, &mut ChunkLayer, &mut GameState)>,
+    pending: Query<&PendingJoin>,
+    mut records: ResMut<Records>,
+    settings: Res<Settings>,
 ) {
-    let mut layer = layers.single_mut();
-    let parkour = &mut courses.single_mut();
+    // The fewest platforms any player has left, and whether everyone has
+    // finished, for every course with players on it
+    let mut progress: HashMap<Entity, (i32, bool)> = HashMap::new();
+    // Messages for everyone on a course
+    let mut announcements: Vec<(Entity, Text)> = Vec::new();
+    let mut on_course: HashSet<(Entity, Uuid)> = HashSet::new();
 
-    let mut platforms_left = i32::MAX;
-    let mut all_finished = true;
+    for (mut client, mut pos, mut look, player_id, username, layer, spectating) in &mut clients {
+        let Ok((_, _, mut parkour)) = courses.get_mut(layer.0) else {
+            continue;
+        };
+        on_course.insert((layer.0, player_id.0));
 
-    for (mut client, mut pos, mut look, player_id) in &mut clients {
         // Get the player's current state
         let player_update = parkour.update_player_state(player_id.0, pos.as_mut(), look.as_mut());
 
         match player_update {
+            // The action bar shows the run they're watching instead
+            PlayerStateUpdate::Paused if spectating => {}
+            PlayerStateUpdate::Paused
+                if parkour
+                    .elimination
+                    .as_ref()
+                    .is_some_and(|elimination| elimination.is_eliminated(&player_id.0)) =>
+            {
+                client.send_action_bar_message(
+                    "You were eliminated. Spectating until the round ends.".color(Color::GRAY),
+                );
+            }
+            PlayerStateUpdate::Paused
+                if parkour
+                    .relays
+                    .iter()
+                    .any(|relay| relay.is_waiting(&player_id.0)) =>
+            {
+                client.send_action_bar_message(
+                    "Waiting for your turn in the relay.".color(Color::GRAY),
+                );
+            }
             PlayerStateUpdate::Paused => {
                 client.send_action_bar_message(
                     "Game paused. Use ".color(Color::WHITE).not_bold()
@@ -544,7 +3462,16 @@ fn manage_course(
                         + " to continue.".color(Color::WHITE).not_bold(),
                 );
             }
-            PlayerStateUpdate::Skipped => {
+            PlayerStateUpdate::Skipped(skipped) => {
+                eprintln!("{} skipped {skipped} platforms", username.0);
+
+                if parkour.skip_policy == SkipPolicy::Warn {
+                    client.send_chat_message(
+                        format!("You skipped {skipped} platforms. ").color(Color::RED)
+                            + "Skips are logged.".color(Color::GRAY),
+                    );
+                }
+
                 client.play_sound(
                     Sound::EntityExperienceOrbPickup,
                     SoundCategory::Block,
@@ -558,6 +3485,15 @@ fn manage_course(
                         + parkour.current_platform(&player_id.0).color(Color::GOLD),
                 );
             }
+            PlayerStateUpdate::SkipRefused(skipped) => {
+                eprintln!("{} was sent back for skipping {skipped} platforms", username.0);
+
+                client.set_title_times(0, 20, 5);
+                client.set_title("");
+                client.set_subtitle(
+                    format!("No skipping! ({skipped} platforms)").color(Color::RED),
+                );
+            }
             PlayerStateUpdate::OnCourse => {
                 client.send_action_bar_message(
                     "Platform: ".into_text()
@@ -567,12 +3503,23 @@ fn manage_course(
                 );
             }
             PlayerStateUpdate::OnPastPlatform => {
-                client.send_action_bar_message(
-                    "You are currently going backwards!".color(Color::RED),
-                );
+                if parkour.team_color(&player_id.0).is_some() {
+                    client.send_action_bar_message(
+                        "Your team made it to platform ".color(Color::YELLOW)
+                            + parkour.current_platform(&player_id.0).color(Color::LIGHT_PURPLE)
+                            + ", catch up!".color(Color::YELLOW),
+                    );
+                } else {
+                    client.send_action_bar_message(
+                        "You are currently going backwards!".color(Color::RED),
+                    );
+                }
             }
             PlayerStateUpdate::Finished => {
-                client.send_action_bar_message("You have finished the course!".color(Color::GREEN));
+                client.send_action_bar_message(
+                    "You have finished the course! ".color(Color::GREEN)
+                        + format!("({} falls)", parkour.falls(&player_id.0)).color(Color::GRAY),
+                );
             }
             PlayerStateUpdate::Finishing => {
                 client.play_sound(
@@ -582,54 +3529,551 @@ fn manage_course(
                     1.0,
                     1.0,
                 );
+
+                if parkour.practicing(&player_id.0) {
+                    client.send_chat_message(
+                        "You finished your practice run in ".color(Color::YELLOW)
+                            + format_duration(parkour.elapsed(&player_id.0)).color(Color::GOLD)
+                            + ". Practice runs aren't recorded.".color(Color::YELLOW),
+                    );
+                }
+
+                if let Some(record) = parkour.run_record(player_id.0, &username.0) {
+                    records.add(record.clone());
+
+                    client.send_chat_message(
+                        "You finished the course in ".color(Color::GREEN)
+                            + format_duration(record.time).color(Color::GOLD).bold()
+                            + " with ".color(Color::GREEN).not_bold()
+                            + if record.deathless() {
+                                "no falls. Deathless!".color(Color::GOLD).bold()
+                            } else {
+                                format!("{} falls.", record.falls)
+                                    .color(Color::LIGHT_PURPLE)
+                                    .not_bold()
+                            },
+                    );
+                }
+
+                if let Some((team, time)) = parkour.finish_team(&player_id.0) {
+                    announcements.push((
+                        layer.0,
+                        "Team ".color(Color::GREEN)
+                            + team.bold()
+                            + " finished the course in ".color(Color::GREEN).not_bold()
+                            + format_duration(time).color(Color::GOLD).bold(),
+                    ));
+                }
+            }
+            PlayerStateUpdate::TeleportedBack => {
+                let falls = parkour.falls(&player_id.0);
+
+                client.play_sound(
+                    Sound::EntityPlayerHurt,
+                    SoundCategory::Player,
+                    pos.0,
+                    1.0,
+                    0.8,
+                );
+
+                client.set_title_times(0, 20, 5);
+                client.set_title("");
+                client.set_subtitle(format!("Fall #{falls}").color(Color::RED));
             }
-            _ => {}
         }
 
-        platforms_left = parkour.platforms_left(&player_id.0).min(platforms_left);
+        let (platforms_left, all_finished) =
+            progress.entry(layer.0).or_insert((i32::MAX, true));
+        *platforms_left = parkour.platforms_left(&player_id.0).min(*platforms_left);
         if !parkour.finished(&player_id.0) {
+            *all_finished = false;
+        }
+    }
+
+    for (course, announcement) in announcements {
+        for (mut client, .., layer, _) in &mut clients {
+            if layer.0 == course {
+                client.send_chat_message(announcement.clone());
+            }
+        }
+    }
+
+    for (entity, mut layer, mut parkour) in &mut courses {
+        // Players who left the course don't count towards anything anymore
+        let absent = parkour
+            .player_states
+            .keys()
+            .filter(|player_id| !on_course.contains(&(entity, **player_id)))
+            .copied()
+            .collect::<Vec<_>>();
+        for player_id in absent {
+            parkour.remove_player(&player_id);
+        }
+
+        let (mut platforms_left, mut all_finished) =
+            progress.get(&entity).copied().unwrap_or((i32::MAX, true));
+
+        // Players waiting in the lobby for this course are still at its start
+        if pending.iter().any(|pending| pending.0 == entity) {
+            platforms_left = platforms_left.min(parkour.course.len());
             all_finished = false;
         }
+
+        if parkour.done() {
+            // The course is created. Therefore, we see whether all players have finished.
+            // If so, we spawn an entire new course.
+
+            // Running rounds reset the course themselves once they're over
+            if all_finished && !parkour.round_running() {
+                for (_, mut pos, _, player_id, _, player_layer, _) in &mut clients {
+                    if player_layer.0 == entity {
+                        parkour.reset_player(player_id.0, pos.as_mut());
+                    }
+                }
+
+                parkour.reset_course(&mut layer);
+            }
+        } else {
+            // Pick what comes next based on how the players are doing
+            parkour.adapt_difficulty();
+
+            // Spawn the platforms
+            while platforms_left < settings.lookahead {
+                platforms_left += 1;
+                if !parkour.spawn_platform(&mut layer) {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn run_races(
+    mut clients: Query<(&mut Client, &mut Position, &UniqueId, &VisibleChunkLayer)>,
+    mut courses: Query<(Entity, &mut GameState)>,
+) {
+    for (entity, mut state) in &mut courses {
+        let Some(mut race) = state.race.take() else {
+            continue;
+        };
+
+        let tick = race.tick();
+
+        for (mut client, mut pos, player_id, layer) in &mut clients {
+            if layer.0 != entity || !race.is_participant(&player_id.0) {
+                continue;
+            }
+
+            match tick {
+                RaceTick::Countdown(step) => {
+                    // Everyone is held on the start platform until the race starts
+                    pos.set(state.course.get_start());
+
+                    if let Some(step) = step {
+                        client.set_title_times(0, 20, 0);
+                        client.set_title(step.color(Color::GOLD).bold());
+                        client.play_sound(
+                            Sound::BlockNoteBlockPling,
+                            SoundCategory::Master,
+                            pos.0,
+                            1.0,
+                            1.0,
+                        );
+                    }
+                }
+                RaceTick::Go => {
+                    // Resetting everyone on the same tick starts all timers together
+                    state.reset_player(player_id.0, pos.as_mut());
+
+                    client.set_title_times(0, 20, 10);
+                    client.set_title("GO!".color(Color::GREEN).bold());
+                    client.play_sound(
+                        Sound::BlockNoteBlockPling,
+                        SoundCategory::Master,
+                        pos.0,
+                        1.0,
+                        2.0,
+                    );
+                }
+                RaceTick::Running => {}
+            }
+        }
+
+        if race.counting_down() || !race.all_finished(&state.player_states) {
+            state.race = Some(race);
+            continue;
+        }
+
+        let standings = race.standings(&state.player_states);
+        for (mut client, _, _, layer) in &mut clients {
+            if layer.0 == entity {
+                client.send_chat_message(standings.clone());
+            }
+        }
     }
+}
+
+fn run_eliminations(
+    mut clients: Query<(
+        &mut Client,
+        &mut Position,
+        &mut GameMode,
+        &UniqueId,
+        &VisibleChunkLayer,
+    )>,
+    mut courses: Query<(Entity, &mut ChunkLayer, &mut GameState)>,
+) {
+    for (entity, mut layer, mut state) in &mut courses {
+        let Some(mut elimination) = state.elimination.take() else {
+            continue;
+        };
+
+        elimination.advance(state.course.len() - 1);
+
+        if elimination.crumbles() {
+            state.course.crumble_to(elimination.front(), &mut layer);
+        }
 
-    if parkour.done() {
-        // The course is created. Therefore, we see whether all players have finished.
-        // If so, we spawn an entire new course.
+        for (player_id, _) in elimination.eliminate(&state.player_states) {
+            state.pause(player_id);
 
-        if all_finished {
-            for (_, mut pos, _, player_id) in &mut clients {
-                parkour.reset_player(player_id.0, pos.as_mut());
+            for (mut client, _, mut game_mode, client_id, client_layer) in &mut clients {
+                if client_id.0 != player_id || client_layer.0 != entity {
+                    continue;
+                }
+
+                *game_mode = GameMode::Spectator;
+
+                client.set_title_times(0, 40, 10);
+                client.set_title("Eliminated!".color(Color::RED).bold());
+                client.set_subtitle(
+                    "The void caught up with you on platform ".color(Color::GRAY)
+                        + elimination.front().color(Color::LIGHT_PURPLE),
+                );
             }
+        }
 
-            parkour.reset_course(&mut layer);
+        if !elimination.over(&state.player_states) {
+            state.elimination = Some(elimination);
+            continue;
         }
-    } else {
-        // Spawn the platforms
-        while platforms_left < 1500 {
-            platforms_left += 1;
-            if !parkour.spawn_platform(&mut layer) {
-                break;
+
+        let results = elimination.results();
+        for (mut client, mut pos, mut game_mode, player_id, client_layer) in &mut clients {
+            if client_layer.0 == entity {
+                state.reset_player(player_id.0, pos.as_mut());
+                *game_mode = GameMode::Adventure;
+
+                client.send_chat_message(results.clone());
+            }
+        }
+
+        state.reset_course(&mut layer);
+    }
+}
+
+fn run_relays(
+    mut clients: Query<(
+        &mut Client,
+        &mut Position,
+        &mut GameMode,
+        &UniqueId,
+        &VisibleChunkLayer,
+    )>,
+    mut courses: Query<(Entity, &mut GameState)>,
+    mut records: ResMut<Records>,
+) {
+    for (entity, mut state) in &mut courses {
+        let mut relays = std::mem::take(&mut state.relays);
+
+        relays.retain_mut(|relay| {
+            let runner = relay.runner();
+
+            let Some(runner_state) = state.player_states.get(&runner) else {
+                // The runner left the course, so the relay can't go on
+                for (mut client, mut pos, mut game_mode, player_id, layer) in &mut clients {
+                    if layer.0 == entity && relay.runners().contains(&player_id.0) {
+                        state.reset_player(player_id.0, pos.as_mut());
+                        *game_mode = GameMode::Adventure;
+
+                        client.send_chat_message(
+                            "Your relay was abandoned, a runner left.".color(Color::RED),
+                        );
+                    }
+                }
+
+                return false;
+            };
+
+            if !relay.is_starting() {
+                let leg_end = relay.leg_end();
+                if runner_state.current_platform() < leg_end && !runner_state.finished() {
+                    return true;
+                }
+
+                let time = runner_state.elapsed();
+                let falls = runner_state.falls();
+                let finished = runner_state.finished();
+                let leg = relay.leg();
+
+                // Reaching the finish ends the relay, even with legs left
+                if relay.hand_off(time).is_none() || finished {
+                    let total = relay.total();
+                    let falls = relay
+                        .runners()
+                        .iter()
+                        .map(|runner| state.falls(runner))
+                        .sum();
+
+                    records.add(RunRecord {
+                        player: runner,
+                        username: relay.team.clone(),
+                        time: total,
+                        falls,
+                        platforms: leg_end.min(state.course.len() - 1),
+                        team: Some(relay.team.clone()),
+                        course: Some(state.name.clone()),
+                    });
+
+                    for (mut client, mut pos, mut game_mode, player_id, layer) in &mut clients {
+                        if layer.0 != entity {
+                            continue;
+                        }
+
+                        if relay.runners().contains(&player_id.0) {
+                            state.reset_player(player_id.0, pos.as_mut());
+                            *game_mode = GameMode::Adventure;
+                        }
+
+                        client.send_chat_message(
+                            "Team ".color(Color::GREEN)
+                                + relay.team.clone().color(Color::AQUA).bold()
+                                + " finished their relay in ".color(Color::GREEN).not_bold()
+                                + format_duration(total).color(Color::GOLD).bold(),
+                        );
+                    }
+
+                    return false;
+                }
+
+                state.pause(runner);
+                // If it is, the next runner is started right away below
+                let generated = state.course.jump(leg_end).is_some();
+
+                for (mut client, _, mut game_mode, player_id, layer) in &mut clients {
+                    if layer.0 != entity || !relay.runners().contains(&player_id.0) {
+                        continue;
+                    }
+
+                    if player_id.0 == runner {
+                        *game_mode = GameMode::Spectator;
+                    }
+
+                    client.send_chat_message(
+                        format!("Leg {} done in ", leg + 1).color(Color::YELLOW)
+                            + format_duration(time).color(Color::GOLD)
+                            + format!(" with {falls} falls.").color(Color::YELLOW),
+                    );
+                    if !generated {
+                        client.send_chat_message(
+                            "Waiting for the next leg to be generated...".color(Color::GRAY),
+                        );
+                    }
+                }
+            }
+
+            // Start the next runner once the platform they start from exists
+            if !relay.is_starting() {
+                return true;
+            }
+
+            let next = relay.runner();
+            let leg_start = relay.leg_start();
+            for (mut client, mut pos, mut game_mode, player_id, layer) in &mut clients {
+                if layer.0 != entity || player_id.0 != next {
+                    continue;
+                }
+
+                if !state.start_player_at(next, leg_start, pos.as_mut()) {
+                    break;
+                }
+                relay.started();
+                *game_mode = GameMode::Adventure;
+
+                client.set_title_times(0, 20, 10);
+                client.set_title("GO!".color(Color::GREEN).bold());
+                client.set_subtitle(
+                    format!("Leg {} of {}", relay.leg() + 1, relay.legs()).color(Color::YELLOW),
+                );
             }
+
+            true
+        });
+
+        state.relays = relays;
+    }
+}
+
+fn update_spectators(
+    mut spectators: Query<(
+        Entity,
+        &mut Client,
+        &Spectating,
+        &EntityId,
+        &VisibleChunkLayer,
+    )>,
+    targets: Query<(&Username, &UniqueId, &VisibleChunkLayer)>,
+    states: Query<&GameState>,
+    mut commands: Commands,
+) {
+    for (entity, mut client, spectating, entity_id, layer) in &mut spectators {
+        let target = targets
+            .get(spectating.0)
+            .ok()
+            .filter(|(.., target_layer)| target_layer.0 == layer.0);
+
+        let (Some((username, target_id, _)), Ok(state)) = (target, states.get(layer.0)) else {
+            // The target left, so there is nothing to watch anymore
+            set_camera(&mut client, entity_id);
+            commands.entity(entity).remove::<Spectating>();
+
+            client.send_chat_message(
+                "The player you were spectating left the course. Use ".color(Color::YELLOW)
+                    + "/resume".on_click_run_command("/resume").color(Color::AQUA)
+                    + " to get back to your run.".color(Color::YELLOW),
+            );
+            continue;
+        };
+
+        client.send_action_bar_message(
+            username.0.clone().color(Color::AQUA)
+                + " - Platform: ".color(Color::WHITE)
+                + state.current_platform(&target_id.0).color(Color::LIGHT_PURPLE)
+                + " - Time: ".color(Color::WHITE)
+                + format_duration(state.elapsed(&target_id.0)).color(Color::GOLD),
+        );
+    }
+}
+
+fn update_sidebars(
+    mut clients: Query<(&mut Client, &mut Sidebar, &UniqueId, &VisibleChunkLayer)>,
+    usernames: Query<(&UniqueId, &Username)>,
+    states: Query<&GameState>,
+) {
+    let usernames = usernames
+        .iter()
+        .map(|(id, username)| (id.0, username.0.clone()))
+        .collect::<HashMap<_, _>>();
+
+    for (mut client, mut sidebar, player_id, layer) in &mut clients {
+        let Ok(state) = states.get(layer.0) else {
+            sidebar.hide(&mut client);
+            continue;
+        };
+
+        let elapsed = state.elapsed(&player_id.0).as_secs();
+
+        let mut lines = vec![
+            "Platform: ".color(Color::WHITE)
+                + state
+                    .current_platform(&player_id.0)
+                    .color(Color::LIGHT_PURPLE),
+            "Ahead: ".color(Color::WHITE)
+                + (state.platforms_left(&player_id.0) - 1).color(Color::LIGHT_PURPLE),
+            "Time: ".color(Color::WHITE)
+                + format!("{}:{:02}", elapsed / 60, elapsed % 60).color(Color::LIGHT_PURPLE),
+            "Falls: ".color(Color::WHITE)
+                + state.falls(&player_id.0).color(Color::LIGHT_PURPLE),
+        ];
+
+        if let Some(elimination) = &state.elimination {
+            lines.push("Void: ".color(Color::RED) + elimination.front().color(Color::LIGHT_PURPLE));
         }
+
+        lines.push(Text::default());
+        lines.push("Top players:".color(Color::YELLOW));
+
+        let leaders = state
+            .leaders(5)
+            .into_iter()
+            .filter_map(|(player_id, platform)| Some((usernames.get(&player_id)?, platform)));
+
+        for (i, (username, platform)) in leaders.enumerate() {
+            lines.push(
+                format!("{}. ", i + 1).color(Color::GRAY)
+                    + username.clone().color(Color::WHITE)
+                    + " "
+                    + platform.color(Color::LIGHT_PURPLE),
+            );
+        }
+
+        sidebar.update(
+            &mut client,
+            "Tini parkour".color(Color::YELLOW).bold(),
+            lines,
+        );
+    }
+}
+
+fn update_progress_bars(
+    mut clients: Query<(&mut Client, &mut ProgressBar, &UniqueId, &VisibleChunkLayer)>,
+    states: Query<&GameState>,
+) {
+    for (mut client, mut progress_bar, player_id, layer) in &mut clients {
+        let Ok(state) = states.get(layer.0) else {
+            progress_bar.hide(&mut client);
+            continue;
+        };
+
+        let platform = state.current_platform(&player_id.0);
+        let total = (state.course.len() - 1).max(1);
+
+        let title = if state.done() {
+            "Platform ".color(Color::WHITE)
+                + platform.color(Color::LIGHT_PURPLE)
+                + " / ".color(Color::WHITE)
+                + total.color(Color::LIGHT_PURPLE)
+        } else {
+            "generating… ".color(Color::YELLOW)
+                + (state.platforms_left(&player_id.0) - 1).color(Color::LIGHT_PURPLE)
+                + " platforms ahead".color(Color::YELLOW)
+        };
+
+        progress_bar.update(
+            &mut client,
+            title,
+            platform as f32 / total as f32,
+            difficulty_color(state.course.difficulty()),
+        );
     }
 }
 
 fn handle_chunk_loads(
     mut events: EventReader<ChunkLoadEvent>,
-    mut layers: Query<&mut ChunkLayer, With<AnvilLevel>>,
-    mut state: Query<&mut GameState>,
+    mut layers: Query<(&mut ChunkLayer, Option<&mut GameState>), With<AnvilLevel>>,
+    mut terrain: ResMut<TerrainCache>,
+    settings: Res<Settings>,
 ) {
-    let mut layer = layers.single_mut();
-
     for event in events.read() {
+        let Ok((mut layer, state)) = layers.get_mut(event.chunk_layer) else {
+            continue;
+        };
+
         match &event.status {
             ChunkLoadStatus::Success { .. } => {
-                // The chunk was inserted into the world. Nothing for us to do.
+                // The chunk was inserted into the world. Later private copies
+                // of courses can start from it.
+                if settings.private {
+                    terrain.store(event.pos, &mut layer, settings.preload_radius);
+                }
             }
             ChunkLoadStatus::Empty => {
                 // There's no chunk here so let's insert an empty chunk. If we were doing
                 // terrain generation we would prepare that here.
                 layer.insert_chunk(event.pos, UnloadedChunk::new());
+                if settings.private {
+                    terrain.store(event.pos, &mut layer, settings.preload_radius);
+                }
             }
             ChunkLoadStatus::Failed(e) => {
                 // Something went wrong.
@@ -645,26 +4089,28 @@ fn handle_chunk_loads(
             }
         };
 
-        for mut state in &mut state.iter_mut() {
-            let course = &mut state.course;
-            course.respawn_course(&mut layer);
+        if let Some(mut state) = state {
+            state.course.respawn_course(&mut layer);
         }
     }
 }
 
 fn override_display_name_to_platform_no(
-    mut clients: Query<(&mut DisplayName, &Username, &UniqueId)>,
-    state: Query<&GameState>,
+    mut clients: Query<(&mut DisplayName, &Username, &UniqueId, &VisibleChunkLayer)>,
+    states: Query<&GameState>,
 ) {
-    let state = state.single();
+    for (mut display_name, username, player_id, layer) in &mut clients {
+        let state = states.get(layer.0).ok();
+
+        let platform = state
+            .map(|state| state.current_platform(&player_id.0))
+            .unwrap_or(0);
+        let color = state
+            .and_then(|state| state.team_color(&player_id.0))
+            .unwrap_or(Color::WHITE);
 
-    for (mut display_name, username, player_id) in &mut clients {
         display_name.0 = Some(
-            username.0.clone().into_text()
-                + " "
-                + state
-                    .current_platform(&player_id.0)
-                    .color(Color::LIGHT_PURPLE),
+            username.0.clone().color(color) + " " + platform.color(Color::LIGHT_PURPLE),
         );
     }
 }
@@ -1,3 +1,9 @@
+use std::{
+    collections::HashMap,
+    ops::Range,
+    time::{Duration, Instant},
+};
+
 use rand::{rngs::SmallRng, seq::SliceRandom, SeedableRng};
 use valence::{
     entity::{Look, Position},
@@ -6,13 +12,48 @@ use valence::{
 };
 
 use crate::{
-    jump::{two_by_two_to_block_pos, Jump},
+    block_chooser::Palettes,
+    difficulty::{Difficulty, Preset},
+    jump::{two_by_two_to_block_pos, Jump, JumpKind},
     port::Port,
+    stats::JumpStats,
 };
 
+/// Per-kind stats of a player are halved once they reach this many attempts,
+/// so that adaptive difficulty follows recent performance.
+const KIND_STATS_WINDOW: u32 = 32;
+
+/// Landing more than this many platforms ahead counts as skipping.
+const MAX_PLATFORMS_AHEAD: i32 = 8;
+
+/// What happens when a player skips platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SkipPolicy {
+    #[default]
+    Allow,
+    /// Allow the skip, but tell the player it was noticed.
+    Warn,
+    /// Teleport the player back to where they skipped from.
+    Refuse,
+}
+
+impl SkipPolicy {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "allow" => Ok(SkipPolicy::Allow),
+            "warn" => Ok(SkipPolicy::Warn),
+            "refuse" => Ok(SkipPolicy::Refuse),
+            _ => Err(format!("unknown skip policy `{name}`, use allow, warn or refuse")),
+        }
+    }
+}
+
 pub enum PlayerStateUpdate {
     OnCourse,
-    Skipped,
+    /// Landed this many platforms ahead, and the skip was allowed.
+    Skipped(i32),
+    /// Tried to skip this many platforms, and was teleported back.
+    SkipRefused(i32),
     OnPastPlatform,
     TeleportedBack,
     Paused,
@@ -20,11 +61,21 @@ pub enum PlayerStateUpdate {
     Finished,
 }
 
-fn shuffled_options(start: Port, layer: &ChunkLayer) -> Vec<Jump> {
-    let mut possible = start.possible_next_jumps(layer);
-    possible.shuffle(&mut SmallRng::from_entropy());
-
+fn shuffled_options(
+    start: Port,
+    layer: &ChunkLayer,
+    difficulty: &Difficulty,
+    palettes: &Palettes,
+    rng: &mut SmallRng,
+) -> Vec<Jump> {
+    let possible = start.possible_next_jumps(layer, &difficulty.kinds(), palettes);
+
+    // A weighted shuffle: rarer kinds tend to end up further back, so they
+    // are only used when the more common ones don't fit.
     possible
+        .choose_multiple_weighted(rng, possible.len(), |jump| difficulty.weight(jump.kind))
+        .map(|shuffled| shuffled.cloned().collect())
+        .unwrap_or(possible)
 }
 
 fn valid_jump(jump: &Jump, previous: &Vec<Jump>, layer: &mut ChunkLayer) -> bool {
@@ -50,15 +101,24 @@ fn valid_jump(jump: &Jump, previous: &Vec<Jump>, layer: &mut ChunkLayer) -> bool
         && !jump.too_high()
 }
 
-fn build_jump_tree(tree: &mut Vec<Jump>, layer: &mut ChunkLayer, depth: u32) -> bool {
-    for new_jump in shuffled_options(tree.last().unwrap().endpoint, layer) {
+fn build_jump_tree(
+    tree: &mut Vec<Jump>,
+    layer: &mut ChunkLayer,
+    difficulty: &Difficulty,
+    palettes: &Palettes,
+    rng: &mut SmallRng,
+    depth: u32,
+) -> bool {
+    let start = tree.last().unwrap().endpoint;
+
+    for new_jump in shuffled_options(start, layer, difficulty, palettes, rng) {
         if !valid_jump(&new_jump, tree, layer) {
             continue;
         }
 
         tree.push(new_jump);
 
-        if depth == 0 || build_jump_tree(tree, layer, depth - 1) {
+        if depth == 0 || build_jump_tree(tree, layer, difficulty, palettes, rng, depth - 1) {
             return true;
         }
 
@@ -70,18 +130,74 @@ fn build_jump_tree(tree: &mut Vec<Jump>, layer: &mut ChunkLayer, depth: u32) ->
 pub struct ParkourCourse {
     jumps: Vec<Jump>,
     generated_end: bool,
+    difficulty: Difficulty,
+    palettes: Palettes,
+    length: Option<i32>,
+    /// How many platforms from the start have crumbled away.
+    crumbled: i32,
+    /// The attempts and fails of all jumps together, so far.
+    stats: JumpStats,
+    seed: u64,
+    rng: SmallRng,
 }
 
 impl ParkourCourse {
-    pub fn new(start: BlockPos, layer: &ChunkLayer) -> Self {
+    pub fn new(
+        start: BlockPos,
+        layer: &ChunkLayer,
+        seed: u64,
+        preset: Preset,
+        length: Option<i32>,
+        palettes: Palettes,
+    ) -> Self {
         let mut jumps = Vec::new();
-        jumps.push(two_by_two_to_block_pos(start, layer));
+        jumps.push(two_by_two_to_block_pos(start, layer, &palettes));
         Self {
             jumps,
             generated_end: false,
+            difficulty: Difficulty::new(preset),
+            palettes,
+            length,
+            crumbled: 0,
+            stats: JumpStats::default(),
+            seed,
+            rng: SmallRng::seed_from_u64(seed),
         }
     }
 
+    pub fn difficulty(&self) -> &Difficulty {
+        &self.difficulty
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn stats(&self) -> JumpStats {
+        self.stats
+    }
+
+    /// Generates from a new seed. Meant for a course that was just reset,
+    /// before anything new is generated.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+
+    pub fn preset(&self) -> Preset {
+        self.difficulty.preset()
+    }
+
+    /// How many platforms long the course is, if it isn't endless.
+    pub fn length(&self) -> Option<i32> {
+        self.length
+    }
+
+    /// Endless courses keep generating until no more jumps fit.
+    pub fn endless(&self) -> bool {
+        self.length.is_none()
+    }
+
     pub fn done(&self) -> bool {
         self.generated_end
     }
@@ -112,23 +228,33 @@ impl ParkourCourse {
             }
         }
 
+        // Finite courses end once they're long enough
+        if self.length.is_some_and(|length| self.len() > length) {
+            self.finish(layer);
+            return false;
+        }
+
         // If we can't create a new jump, we're done
-        let jump = build_jump_tree(&mut self.jumps, layer, foresight);
+        let jump = build_jump_tree(
+            &mut self.jumps,
+            layer,
+            &self.difficulty,
+            &self.palettes,
+            &mut self.rng,
+            foresight,
+        );
         if !jump {
             // We're done :3
-            build_jump_tree(&mut self.jumps, layer, foresight - 1);
-
-            // Switch the last one to finish portal mode
-            self.jumps.last_mut().unwrap().set_finish_portal();
-
-            // Set the generated end flag
-            self.generated_end = true;
-
-            // Now, we can just build all of these
-            for jump in self.jumps.iter().rev() {
-                jump.build(layer);
-            }
-
+            build_jump_tree(
+                &mut self.jumps,
+                layer,
+                &self.difficulty,
+                &self.palettes,
+                &mut self.rng,
+                foresight - 1,
+            );
+
+            self.finish(layer);
             return false;
         }
 
@@ -144,13 +270,101 @@ impl ParkourCourse {
         true
     }
 
+    fn finish(&mut self, layer: &mut ChunkLayer) {
+        // Switch the last one to finish portal mode
+        self.jumps.last_mut().unwrap().set_finish_portal();
+
+        // Set the generated end flag
+        self.generated_end = true;
+
+        // Now, we can just build all of these
+        for jump in self.jumps.iter().rev() {
+            jump.build(layer);
+        }
+    }
+
     pub fn len(&self) -> i32 {
         self.jumps.len() as i32
     }
 
+    pub fn jumps(&self) -> &[Jump] {
+        &self.jumps
+    }
+
+    /// The platform the position is on, if any.
+    pub fn platform_at(&self, pos: DVec3) -> Option<i32> {
+        self.jumps
+            .iter()
+            .position(|jump| jump.endpoint.on_platform(pos))
+            .map(|platform| platform as i32)
+    }
+
+    /// The platforms that can be stood on: neither crumbled away, nor among the
+    /// last ones generated, which are only built once the next are.
+    pub fn built_platforms(&self) -> Range<i32> {
+        let end = if self.generated_end {
+            self.len()
+        } else {
+            self.len() - 2
+        };
+
+        self.crumbled..end.max(self.crumbled)
+    }
+
+    pub fn jump(&self, platform: i32) -> Option<&Jump> {
+        if platform < 0 {
+            return None;
+        }
+
+        self.jumps.get(platform as usize)
+    }
+
+    pub fn adapt_difficulty(&mut self, stats: &HashMap<JumpKind, JumpStats>) {
+        self.difficulty.adapt(stats);
+    }
+
+    /// Only affects platforms generated from now on.
+    pub fn set_jump_table(&mut self, jump_table: HashMap<JumpKind, f64>) {
+        self.difficulty.set_jump_table(jump_table);
+    }
+
+    /// Only affects platforms generated from now on.
+    pub fn set_palettes(&mut self, palettes: Palettes) {
+        self.palettes = palettes;
+    }
+
+    pub fn record_attempt(&mut self, platform: i32, failed: bool) {
+        if platform < 1 {
+            return;
+        }
+
+        if let Some(jump) = self.jumps.get_mut(platform as usize) {
+            jump.stats.record(failed);
+            self.stats.record(failed);
+        }
+    }
+
+    /// Removes every platform before the given one.
+    pub fn crumble_to(&mut self, platform: i32, layer: &mut ChunkLayer) {
+        let platform = platform.min(self.len());
+        if platform <= self.crumbled {
+            return;
+        }
+
+        for jump in &self.jumps[self.crumbled as usize..platform as usize] {
+            jump.despawn(layer);
+        }
+
+        self.crumbled = platform;
+    }
+
     pub fn respawn_course(&mut self, layer: &mut ChunkLayer) {
-        for jump in self.jumps.iter().rev().skip(3) {
-            jump.build(layer);
+        let crumbled = self.crumbled as usize;
+
+        for (i, jump) in self.jumps.iter().enumerate().rev().skip(3) {
+            if i >= crumbled {
+                jump.build(layer);
+            }
         }
     }
 
@@ -163,6 +377,10 @@ impl ParkourCourse {
         self.jumps.truncate(1);
 
         self.generated_end = false;
+        self.crumbled = 0;
+        self.stats = JumpStats::default();
+        // Start over from the seed, which the course is reported by
+        self.rng = SmallRng::seed_from_u64(self.seed);
     }
 }
 
@@ -172,6 +390,14 @@ pub struct PlayerOnCourse {
     last_valid_look: Vec3,
     paused: bool,
     finished: bool,
+    kind_stats: HashMap<JumpKind, JumpStats>,
+    falls: u32,
+    falls_per_platform: HashMap<i32, u32>,
+    started: Instant,
+    paused_at: Option<Instant>,
+    finished_in: Option<Duration>,
+    /// Practice runs can move their checkpoint freely and aren't recorded.
+    practice: bool,
 }
 
 impl PlayerOnCourse {
@@ -186,6 +412,22 @@ impl PlayerOnCourse {
             last_valid_look: Vec3::new(1.0, 0.0, 0.0),
             paused: false,
             finished: false,
+            kind_stats: HashMap::new(),
+            falls: 0,
+            falls_per_platform: HashMap::new(),
+            started: Instant::now(),
+            paused_at: None,
+            finished_in: None,
+            practice: false,
+        }
+    }
+
+    /// A fresh run starting on a platform further along the course.
+    pub fn starting_at(platform: i32, position: DVec3) -> Self {
+        Self {
+            last_platform: platform,
+            last_valid_position: position,
+            ..Self::new(position.into())
         }
     }
 
@@ -202,6 +444,7 @@ impl PlayerOnCourse {
         course: &ParkourCourse,
         player_pos: &mut Position,
         player_look: &mut Look,
+        skip_policy: SkipPolicy,
     ) -> PlayerStateUpdate {
         if self.finished {
             return PlayerStateUpdate::Finished;
@@ -219,17 +462,25 @@ impl PlayerOnCourse {
                 } else if i == self.last_platform as usize {
                     if i == course.len() as usize - 1 && course.done() {
                         self.finished = true;
+                        self.finished_in = Some(self.elapsed());
                         return PlayerStateUpdate::Finishing;
                     }
 
                     return PlayerStateUpdate::OnCourse;
                 }
 
-                if i > self.last_platform as usize + 8 {
+                let skipped = i as i32 - self.last_platform;
+                if skipped > MAX_PLATFORMS_AHEAD {
+                    // Practice runs move around the course freely anyway
+                    if skip_policy == SkipPolicy::Refuse && !self.practice {
+                        self.to_last_checkpoint(player_pos, player_look);
+                        return PlayerStateUpdate::SkipRefused(skipped);
+                    }
+
                     self.last_platform = i as i32;
                     self.last_valid_position = player_pos.get();
                     self.last_valid_look = player_look.vec();
-                    return PlayerStateUpdate::Skipped;
+                    return PlayerStateUpdate::Skipped(skipped);
                 }
 
                 self.last_platform = i as i32;
@@ -248,24 +499,102 @@ impl PlayerOnCourse {
         }
 
         // Player is off course
-        self.to_last_checkpoint(player_pos, player_look);
+        self.fall(player_pos, player_look);
         return PlayerStateUpdate::TeleportedBack;
     }
 
+    /// Moves the checkpoint up to a teammate's, if they got further.
+    pub fn share_checkpoint(&mut self, teammate: &PlayerOnCourse) {
+        if self.finished || teammate.last_platform <= self.last_platform {
+            return;
+        }
+
+        self.last_platform = teammate.last_platform;
+        self.last_valid_position = teammate.last_valid_position;
+        self.last_valid_look = teammate.last_valid_look;
+    }
+
+    pub fn set_checkpoint(&mut self, platform: i32, position: DVec3, look: Vec3) {
+        self.last_platform = platform;
+        self.last_valid_position = position;
+        self.last_valid_look = look;
+    }
+
+    pub fn practice(&self) -> bool {
+        self.practice
+    }
+
+    pub fn set_practice(&mut self, practice: bool) {
+        self.practice = practice;
+    }
+
     pub fn to_last_checkpoint(&self, player_pos: &mut Position, player_look: &mut Look) {
         player_pos.set(self.last_valid_position);
         player_look.set_vec(self.last_valid_look);
     }
 
+    /// Counts a fall from the current platform and teleports back to it.
+    pub fn fall(&mut self, player_pos: &mut Position, player_look: &mut Look) {
+        self.falls += 1;
+        *self
+            .falls_per_platform
+            .entry(self.last_platform)
+            .or_default() += 1;
+
+        self.to_last_checkpoint(player_pos, player_look);
+    }
+
+    pub fn falls(&self) -> u32 {
+        self.falls
+    }
+
+    pub fn falls_on(&self, platform: i32) -> u32 {
+        self.falls_per_platform.get(&platform).copied().unwrap_or(0)
+    }
+
+    /// The time spent on the course, not counting the time spent paused.
+    pub fn elapsed(&self) -> Duration {
+        if let Some(finished_in) = self.finished_in {
+            return finished_in;
+        }
+
+        match self.paused_at {
+            Some(paused_at) => paused_at - self.started,
+            None => self.started.elapsed(),
+        }
+    }
+
     pub fn pause(&mut self) {
+        if !self.paused {
+            self.paused_at = Some(Instant::now());
+        }
+
         self.paused = true;
     }
 
     pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.started += paused_at.elapsed();
+        }
+
         self.paused = false;
     }
 
     pub fn finished(&self) -> bool {
         self.finished
     }
+
+    pub fn kind_stats(&self) -> &HashMap<JumpKind, JumpStats> {
+        &self.kind_stats
+    }
+
+    pub fn record_attempt(&mut self, kind: JumpKind, failed: bool) {
+        let stats = self.kind_stats.entry(kind).or_default();
+        stats.record(failed);
+
+        if stats.attempts >= KIND_STATS_WINDOW {
+            stats.attempts /= 2;
+            stats.fails /= 2;
+        }
+    }
 }
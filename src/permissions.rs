@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+
+use serde::Deserialize;
+use valence::command::CommandScopeRegistry;
+use valence::prelude::*;
+
+/// The group of players not assigned to any other.
+pub const DEFAULT_GROUP: &str = "player";
+
+/// The group operators from `ops.json` are in.
+pub const OPERATOR_GROUP: &str = "admin";
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+struct Group {
+    /// A group whose scopes this one has as well.
+    inherits: Option<String>,
+    #[serde(default)]
+    scopes: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct PermissionsFile {
+    groups: HashMap<String, Group>,
+    /// The group of every player by UUID.
+    players: HashMap<Uuid, String>,
+}
+
+/// Permission groups, each a set of command scopes, and which players are in
+/// them.
+#[derive(Resource)]
+pub struct Permissions {
+    groups: HashMap<String, Group>,
+    players: HashMap<Uuid, String>,
+}
+
+impl Permissions {
+    /// Loads the groups from `path` on top of the built-in player, moderator
+    /// and admin groups. A missing file just means the built-in groups.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let file = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|e| format!("Invalid `{}`: {e}", path.display()))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => PermissionsFile::default(),
+            Err(e) => return Err(format!("Failed to read `{}`: {e}.", path.display())),
+        };
+
+        let mut groups = built_in_groups();
+        groups.extend(file.groups);
+
+        for (name, group) in &groups {
+            // Walking more steps than there are groups means going in circles
+            let mut parent = group.inherits.as_ref();
+            for _ in 0..=groups.len() {
+                let Some(parent_name) = parent else {
+                    break;
+                };
+                let Some(parent_group) = groups.get(parent_name) else {
+                    return Err(format!("Group `{name}` inherits unknown group `{parent_name}`."));
+                };
+                parent = parent_group.inherits.as_ref();
+            }
+
+            if parent.is_some() {
+                return Err(format!("Group `{name}` inherits from itself."));
+            }
+        }
+
+        if let Some((player, group)) = file
+            .players
+            .iter()
+            .find(|(_, group)| !groups.contains_key(*group))
+        {
+            return Err(format!("Player {player} is in unknown group `{group}`."));
+        }
+
+        Ok(Self {
+            groups,
+            players: file.players,
+        })
+    }
+
+    /// Links the scope of every group to the scopes it grants.
+    pub fn register(&self, registry: &mut CommandScopeRegistry) {
+        for (name, group) in &self.groups {
+            for scope in &group.scopes {
+                registry.link(&group_scope(name), scope);
+            }
+
+            if let Some(parent) = &group.inherits {
+                registry.link(&group_scope(name), &group_scope(parent));
+            }
+        }
+    }
+
+    pub fn group_of(&self, player_id: Uuid) -> &str {
+        self.players
+            .get(&player_id)
+            .map_or(DEFAULT_GROUP, String::as_str)
+    }
+}
+
+/// The scope that grants everything a group may do.
+pub fn group_scope(group: &str) -> String {
+    format!("parkour.group.{group}")
+}
+
+fn built_in_groups() -> HashMap<String, Group> {
+    let group = |inherits: Option<&str>, scope: &str| Group {
+        inherits: inherits.map(str::to_owned),
+        scopes: vec![scope.to_owned()],
+    };
+
+    HashMap::from([
+        (DEFAULT_GROUP.to_owned(), group(None, "parkour.command")),
+        (
+            "moderator".to_owned(),
+            group(Some(DEFAULT_GROUP), "parkour.moderate"),
+        ),
+        (
+            OPERATOR_GROUP.to_owned(),
+            group(Some("moderator"), "parkour.admin"),
+        ),
+    ])
+}
@@ -1,6 +1,7 @@
 use valence::{math::DVec3, BlockPos, ChunkLayer};
 
-use crate::jump::{two_by_two_to_block_pos, Jump};
+use crate::block_chooser::Palettes;
+use crate::jump::{two_by_two_to_block_pos, Jump, JumpKind};
 
 #[derive(Debug, Clone, Copy)]
 pub enum Port {
@@ -100,59 +101,35 @@ impl Port {
         }
     }
 
-    pub fn possible_next_jumps(&self, layer: &ChunkLayer) -> Vec<Jump> {
+    pub fn possible_next_jumps(
+        &self,
+        layer: &ChunkLayer,
+        kinds: &[JumpKind],
+        palettes: &Palettes,
+    ) -> Vec<Jump> {
         match self {
             Port::TwoByTwo(platform) => {
-                vec![
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x + 5, platform.y, platform.z),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x, platform.y, platform.z + 5),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x - 5, platform.y, platform.z),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x, platform.y, platform.z - 5),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x + 4, platform.y + 1, platform.z),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x + 4, platform.y - 1, platform.z),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x - 4, platform.y + 1, platform.z),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x - 4, platform.y - 1, platform.z),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x, platform.y + 1, platform.z + 4),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x, platform.y - 1, platform.z + 4),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x, platform.y + 1, platform.z - 4),
-                        layer,
-                    ),
-                    two_by_two_to_block_pos(
-                        BlockPos::new(platform.x, platform.y - 1, platform.z - 4),
-                        layer,
-                    ),
-                ]
+                let mut jumps = Vec::new();
+
+                for kind in kinds {
+                    let (distance, height) = kind.offset();
+
+                    for (x, z) in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+                        let mut jump = two_by_two_to_block_pos(
+                            BlockPos::new(
+                                platform.x + x * distance,
+                                platform.y + height,
+                                platform.z + z * distance,
+                            ),
+                            layer,
+                            palettes,
+                        );
+                        jump.kind = *kind;
+                        jumps.push(jump);
+                    }
+                }
+
+                jumps
             }
             Port::TwoByTwoPortal(_) => Vec::new(),
         }
@@ -0,0 +1,35 @@
+use std::ops::Range;
+
+use valence::command::CommandRegistry;
+use valence::prelude::*;
+use valence::protocol::packets::play::command_tree_s2c::{NodeData, Suggestion};
+
+/// The name of the command argument that takes a platform number.
+pub const PLATFORM_ARG: &str = "platform";
+
+/// At most this many platform numbers are suggested at once.
+const MAX_SUGGESTIONS: usize = 64;
+
+/// Clients only ask the server to complete arguments that are marked for it,
+/// so platform numbers have to be marked once all commands are registered.
+pub fn ask_server_for_platforms(mut registry: ResMut<CommandRegistry>) {
+    for node in registry.graph.graph.node_weights_mut() {
+        if let NodeData::Argument {
+            name, suggestion, ..
+        } = &mut node.data
+        {
+            if name == PLATFORM_ARG {
+                *suggestion = Some(Suggestion::AskServer);
+            }
+        }
+    }
+}
+
+/// Platform numbers starting with what's typed so far, lowest first.
+pub fn platform_suggestions(typed: &str, platforms: Range<i32>) -> Vec<String> {
+    platforms
+        .map(|platform| platform.to_string())
+        .filter(|platform| platform.starts_with(typed))
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
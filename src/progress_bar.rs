@@ -0,0 +1,86 @@
+use std::borrow::Cow;
+
+use valence::prelude::*;
+use valence::protocol::packets::play::boss_bar_s2c::{
+    BossBarAction, BossBarColor, BossBarDivision, BossBarFlags,
+};
+use valence::protocol::packets::play::BossBarS2c;
+use valence::protocol::WritePacket;
+
+use crate::difficulty::Difficulty;
+
+/// Every client only ever sees its own progress bar, so one id is enough.
+const BAR_ID: Uuid = Uuid::from_u128(0x7061726b6f75725f70726f6772657373);
+
+/// The colour for each difficulty level, from easy to a hard course that
+/// adapted to be harder still.
+const LEVEL_COLORS: [BossBarColor; 4] = [
+    BossBarColor::Green,
+    BossBarColor::Yellow,
+    BossBarColor::Red,
+    BossBarColor::Purple,
+];
+
+pub fn difficulty_color(difficulty: &Difficulty) -> BossBarColor {
+    LEVEL_COLORS[difficulty.level().min(LEVEL_COLORS.len() - 1)]
+}
+
+/// A per-client boss bar, only sending the parts that changed.
+#[derive(Component, Default)]
+pub struct ProgressBar {
+    shown: Option<(Text, f32, BossBarColor)>,
+}
+
+impl ProgressBar {
+    pub fn update(&mut self, client: &mut Client, title: Text, progress: f32, color: BossBarColor) {
+        let progress = progress.clamp(0.0, 1.0);
+
+        let Some((old_title, old_progress, old_color)) = &self.shown else {
+            client.write_packet(&BossBarS2c {
+                id: BAR_ID,
+                action: BossBarAction::Add {
+                    title: Cow::Borrowed(&title),
+                    health: progress,
+                    color,
+                    division: BossBarDivision::NoDivision,
+                    flags: BossBarFlags::new(),
+                },
+            });
+
+            self.shown = Some((title, progress, color));
+            return;
+        };
+
+        if *old_title != title {
+            client.write_packet(&BossBarS2c {
+                id: BAR_ID,
+                action: BossBarAction::UpdateTitle(Cow::Borrowed(&title)),
+            });
+        }
+
+        if (*old_progress - progress).abs() > f32::EPSILON {
+            client.write_packet(&BossBarS2c {
+                id: BAR_ID,
+                action: BossBarAction::UpdateHealth(progress),
+            });
+        }
+
+        if *old_color != color {
+            client.write_packet(&BossBarS2c {
+                id: BAR_ID,
+                action: BossBarAction::UpdateStyle(color, BossBarDivision::NoDivision),
+            });
+        }
+
+        self.shown = Some((title, progress, color));
+    }
+
+    pub fn hide(&mut self, client: &mut Client) {
+        if self.shown.take().is_some() {
+            client.write_packet(&BossBarS2c {
+                id: BAR_ID,
+                action: BossBarAction::Remove,
+            });
+        }
+    }
+}
@@ -0,0 +1,36 @@
+use std::collections::VecDeque;
+
+use valence::prelude::*;
+
+/// Players waiting for a free slot, in the order they joined.
+#[derive(Resource, Default)]
+pub struct JoinQueue(VecDeque<Entity>);
+
+impl JoinQueue {
+    pub fn push(&mut self, player: Entity) {
+        self.0.push_back(player);
+    }
+
+    /// The player who has waited the longest.
+    pub fn pop(&mut self) -> Option<Entity> {
+        self.0.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Drops the players for which `keep` returns false, e.g. because they
+    /// left.
+    pub fn retain(&mut self, keep: impl FnMut(&Entity) -> bool) {
+        self.0.retain(keep);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Entity> + '_ {
+        self.0.iter().copied()
+    }
+}
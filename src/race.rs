@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use valence::prelude::*;
+
+use crate::{parkour::PlayerOnCourse, records::format_duration};
+
+/// Ticks between each step of the countdown.
+const COUNTDOWN_STEP: u32 = 20;
+
+const COUNTDOWN_FROM: u32 = 3;
+
+pub enum RaceTick {
+    /// Still counting down; the number to show, if it just changed.
+    Countdown(Option<u32>),
+    /// The countdown just ended; everyone starts now.
+    Go,
+    Running,
+}
+
+/// A synchronized race on a course.
+pub struct Race {
+    participants: Vec<(Uuid, String)>,
+    ticks: u32,
+}
+
+impl Race {
+    pub fn new(participants: Vec<(Uuid, String)>) -> Self {
+        Self {
+            participants,
+            ticks: 0,
+        }
+    }
+
+    pub fn is_participant(&self, player_id: &Uuid) -> bool {
+        self.participants.iter().any(|(id, _)| id == player_id)
+    }
+
+    pub fn counting_down(&self) -> bool {
+        self.ticks < COUNTDOWN_FROM * COUNTDOWN_STEP
+    }
+
+    pub fn tick(&mut self) -> RaceTick {
+        let ticks = self.ticks;
+        self.ticks += 1;
+
+        let countdown_ticks = COUNTDOWN_FROM * COUNTDOWN_STEP;
+
+        if ticks < countdown_ticks {
+            RaceTick::Countdown(
+                (ticks % COUNTDOWN_STEP == 0).then(|| COUNTDOWN_FROM - ticks / COUNTDOWN_STEP),
+            )
+        } else if ticks == countdown_ticks {
+            RaceTick::Go
+        } else {
+            RaceTick::Running
+        }
+    }
+
+    pub fn all_finished(&self, states: &HashMap<Uuid, PlayerOnCourse>) -> bool {
+        self.participants.iter().all(|(player_id, _)| {
+            states
+                .get(player_id)
+                .map(|state| state.finished())
+                .unwrap_or(true)
+        })
+    }
+
+    /// Finishers ordered by time, then everyone else by how far they got.
+    pub fn standings(&self, states: &HashMap<Uuid, PlayerOnCourse>) -> Text {
+        let mut finished = Vec::new();
+        let mut dnf = Vec::new();
+
+        for (player_id, username) in &self.participants {
+            match states.get(player_id) {
+                Some(state) if state.finished() => finished.push((username, state.elapsed())),
+                Some(state) => dnf.push((username, state.current_platform())),
+                None => dnf.push((username, 0)),
+            }
+        }
+
+        finished.sort_by_key(|(_, time)| *time);
+        dnf.sort_by_key(|(_, platform)| -platform);
+
+        let mut standings = "Race results:".color(Color::YELLOW).bold();
+
+        for (i, (username, time)) in finished.iter().enumerate() {
+            standings = standings
+                + format!("\n{}. ", i + 1).color(Color::WHITE).not_bold()
+                + username.to_string().color(Color::AQUA)
+                + " "
+                + format_duration(*time).color(Color::GOLD);
+        }
+
+        for (username, platform) in dnf {
+            standings = standings
+                + "\nDNF ".color(Color::RED).not_bold()
+                + username.to_string().color(Color::AQUA)
+                + format!(" (platform {platform})").color(Color::GRAY);
+        }
+
+        standings
+    }
+}
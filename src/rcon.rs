@@ -0,0 +1,274 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use valence::command::scopes::CommandScopes;
+use valence::command::CommandExecutionEvent;
+use valence::prelude::*;
+
+use crate::config::Settings;
+use crate::permissions::{group_scope, OPERATOR_GROUP};
+
+const AUTH: i32 = 3;
+const AUTH_RESPONSE: i32 = 2;
+const EXEC_COMMAND: i32 = 2;
+const RESPONSE_VALUE: i32 = 0;
+
+/// The largest packet a client may send, as in vanilla.
+const MAX_REQUEST_LENGTH: usize = 1460;
+
+/// Longer responses are split over multiple packets.
+const MAX_RESPONSE_BODY: usize = 4096;
+
+/// How many ticks a command gets to answer before its response is sent.
+const RESPONSE_TICKS: u8 = 2;
+
+/// A command from outside the game, and where its output goes.
+pub struct RemoteCommand {
+    command: String,
+    output: Sender<String>,
+}
+
+/// Commands from RCON or HTTP clients waiting to be run.
+#[derive(Resource)]
+pub struct RemoteCommands {
+    sender: Sender<RemoteCommand>,
+    receiver: Mutex<Receiver<RemoteCommand>>,
+}
+
+impl Default for RemoteCommands {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            sender,
+            receiver: Mutex::new(receiver),
+        }
+    }
+}
+
+impl RemoteCommands {
+    pub fn sender(&self) -> Sender<RemoteCommand> {
+        self.sender.clone()
+    }
+}
+
+/// Runs a command as an operator and waits for its output, one message per
+/// line. None if the server is shutting down.
+pub fn run_remote_command(commands: &Sender<RemoteCommand>, command: String) -> Option<String> {
+    let (output, lines) = mpsc::channel();
+    commands.send(RemoteCommand { command, output }).ok()?;
+
+    // Ends once the executor is despawned and drops its sender
+    Some(lines.iter().collect::<Vec<_>>().join("\n"))
+}
+
+/// The executor of a command sent over RCON or HTTP. Its output is sent back
+/// once the entity is despawned.
+#[derive(Component)]
+pub struct RemoteExecutor {
+    output: Sender<String>,
+    ticks_left: u8,
+}
+
+impl RemoteExecutor {
+    pub fn send(&self, line: String) {
+        // The client may have hung up already
+        let _ = self.output.send(line);
+    }
+}
+
+/// Starts listening for RCON clients on the local port, if enabled.
+pub fn start_rcon(settings: Res<Settings>, remote_commands: Res<RemoteCommands>) {
+    let Some(rcon) = &settings.rcon else {
+        return;
+    };
+
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, rcon.port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("failed to start RCON on port {}: {e}", rcon.port);
+            return;
+        }
+    };
+
+    let sender = remote_commands.sender();
+    let password = rcon.password.clone();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else {
+                continue;
+            };
+
+            let password = password.clone();
+            let commands = sender.clone();
+            thread::spawn(move || {
+                if let Err(e) = handle_connection(stream, &password, &commands) {
+                    if e.kind() != ErrorKind::UnexpectedEof {
+                        eprintln!("RCON connection failed: {e}");
+                    }
+                }
+            });
+        }
+    });
+
+    println!("RCON listening on port {}", rcon.port);
+}
+
+/// Runs the commands RCON and HTTP clients sent as an operator, and sends
+/// back the output of commands that had time to finish.
+pub fn run_remote_commands(
+    remote_commands: Res<RemoteCommands>,
+    mut executors: Query<(Entity, &mut RemoteExecutor)>,
+    mut executions: EventWriter<CommandExecutionEvent>,
+    mut commands: Commands,
+) {
+    for (entity, mut executor) in &mut executors {
+        if executor.ticks_left == 0 {
+            commands.entity(entity).despawn();
+        } else {
+            executor.ticks_left -= 1;
+        }
+    }
+
+    for request in remote_commands.receiver.lock().unwrap().try_iter() {
+        let mut scopes = CommandScopes::default();
+        scopes.add(&group_scope(OPERATOR_GROUP));
+
+        let executor = commands
+            .spawn((
+                RemoteExecutor {
+                    output: request.output,
+                    ticks_left: RESPONSE_TICKS,
+                },
+                scopes,
+            ))
+            .id();
+
+        executions.send(CommandExecutionEvent {
+            command: request.command.trim().trim_start_matches('/').to_owned(),
+            executor,
+        });
+    }
+}
+
+fn handle_connection(
+    mut stream: TcpStream,
+    password: &str,
+    commands: &Sender<RemoteCommand>,
+) -> io::Result<()> {
+    let mut authenticated = false;
+
+    loop {
+        let (id, kind, body) = read_packet(&mut stream)?;
+
+        match kind {
+            AUTH => {
+                authenticated = !password.is_empty() && body == password;
+                let id = if authenticated { id } else { -1 };
+
+                write_packet(&mut stream, id, AUTH_RESPONSE, "")?;
+            }
+            EXEC_COMMAND if authenticated => {
+                let Some(response) = run_remote_command(commands, body) else {
+                    return Ok(());
+                };
+
+                let mut chunk = String::new();
+                for c in response.chars() {
+                    if chunk.len() + c.len_utf8() > MAX_RESPONSE_BODY {
+                        write_packet(&mut stream, id, RESPONSE_VALUE, &chunk)?;
+                        chunk.clear();
+                    }
+                    chunk.push(c);
+                }
+                write_packet(&mut stream, id, RESPONSE_VALUE, &chunk)?;
+            }
+            _ => write_packet(&mut stream, -1, AUTH_RESPONSE, "")?,
+        }
+    }
+}
+
+/// Reads a packet, returning its request ID, type and body.
+fn read_packet(stream: &mut impl Read) -> io::Result<(i32, i32, String)> {
+    let mut length = [0; 4];
+    stream.read_exact(&mut length)?;
+    let length = i32::from_le_bytes(length);
+
+    // The ID, the type and the two terminating zeros
+    if length < 10 || length as usize > MAX_REQUEST_LENGTH {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("invalid packet length {length}"),
+        ));
+    }
+
+    let mut packet = vec![0; length as usize];
+    stream.read_exact(&mut packet)?;
+
+    let id = i32::from_le_bytes(packet[0..4].try_into().unwrap());
+    let kind = i32::from_le_bytes(packet[4..8].try_into().unwrap());
+    let body = String::from_utf8_lossy(&packet[8..packet.len() - 2]).into_owned();
+
+    Ok((id, kind, body))
+}
+
+fn write_packet(stream: &mut impl Write, id: i32, kind: i32, body: &str) -> io::Result<()> {
+    let mut packet = Vec::with_capacity(body.len() + 14);
+    packet.extend(((body.len() + 10) as i32).to_le_bytes());
+    packet.extend(id.to_le_bytes());
+    packet.extend(kind.to_le_bytes());
+    packet.extend(body.as_bytes());
+    packet.extend([0, 0]);
+
+    stream.write_all(&packet)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packets_round_trip() {
+        let mut buf = Vec::new();
+        write_packet(&mut buf, 7, EXEC_COMMAND, "list").unwrap();
+        write_packet(&mut buf, -1, AUTH_RESPONSE, "").unwrap();
+
+        let mut stream = &buf[..];
+        assert_eq!(
+            read_packet(&mut stream).unwrap(),
+            (7, EXEC_COMMAND, "list".to_owned())
+        );
+        assert_eq!(
+            read_packet(&mut stream).unwrap(),
+            (-1, AUTH_RESPONSE, String::new())
+        );
+
+        let e = read_packet(&mut stream).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_bad_lengths() {
+        for length in [-1, 0, 9, MAX_REQUEST_LENGTH as i32 + 1] {
+            let mut packet = length.to_le_bytes().to_vec();
+            packet.extend([0; 16]);
+
+            let e = read_packet(&mut &packet[..]).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_packets() {
+        let mut buf = Vec::new();
+        write_packet(&mut buf, 1, AUTH, "password").unwrap();
+        buf.truncate(buf.len() - 3);
+
+        let e = read_packet(&mut &buf[..]).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::UnexpectedEof);
+    }
+}
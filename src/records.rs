@@ -0,0 +1,198 @@
+use std::{
+    collections::HashMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use valence::prelude::{Resource, Uuid};
+
+/// A finished run, stored one per line in a CSV file.
+#[derive(Debug, Clone)]
+pub struct RunRecord {
+    pub player: Uuid,
+    pub username: String,
+    pub time: Duration,
+    pub falls: u32,
+    pub platforms: i32,
+    /// The team that ran this as a relay, in which case `username` is the
+    /// team name and `player` the last runner.
+    pub team: Option<String>,
+    /// The course the run was on. Runs recorded before courses were stored
+    /// don't have one.
+    pub course: Option<String>,
+}
+
+impl RunRecord {
+    pub fn deathless(&self) -> bool {
+        self.falls == 0
+    }
+
+    fn to_line(&self) -> String {
+        let mut line = format!(
+            "{},{},{},{},{},{}",
+            self.player,
+            self.username,
+            self.time.as_millis(),
+            self.falls,
+            self.platforms,
+            self.team.as_deref().unwrap_or_default()
+        );
+
+        if let Some(course) = &self.course {
+            line += &format!(",{course}");
+        }
+
+        line
+    }
+
+    fn from_line(line: &str) -> Option<Self> {
+        let mut fields = line.split(',');
+
+        Some(Self {
+            player: fields.next()?.parse().ok()?,
+            username: fields.next()?.to_owned(),
+            time: Duration::from_millis(fields.next()?.parse().ok()?),
+            falls: fields.next()?.parse().ok()?,
+            platforms: fields.next()?.parse().ok()?,
+            team: fields
+                .next()
+                .filter(|team| !team.is_empty())
+                .map(str::to_owned),
+            course: fields.next().map(str::to_owned),
+        })
+    }
+}
+
+/// Who a leaderboard entry belongs to.
+#[derive(PartialEq, Eq, Hash)]
+enum Runner<'a> {
+    Player(Uuid),
+    /// A team running relays, whichever of its members ran last.
+    Team(&'a str),
+}
+
+/// All finished runs across every course, backed by a file.
+#[derive(Resource)]
+pub struct Records {
+    path: PathBuf,
+    records: Vec<RunRecord>,
+}
+
+impl Records {
+    /// Loads all the records stored at `path`, skipping any malformed lines.
+    pub fn load(path: &Path) -> Self {
+        let records = match File::open(path) {
+            Ok(file) => BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| RunRecord::from_line(&line))
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+
+        Self {
+            path: path.to_owned(),
+            records,
+        }
+    }
+
+    pub fn add(&mut self, record: RunRecord) {
+        if let Err(e) = self.append(&record) {
+            eprintln!("failed to store the run of {}: {e:#}", record.username);
+        }
+
+        self.records.push(record);
+    }
+
+    fn append(&self, record: &RunRecord) -> std::io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        writeln!(file, "{}", record.to_line())
+    }
+
+    pub fn best(&self, player_id: &Uuid) -> Option<&RunRecord> {
+        self.records
+            .iter()
+            .filter(|record| record.team.is_none() && record.player == *player_id)
+            .min_by_key(|record| record.time)
+    }
+
+    /// The best solo run of every player and the best relay of every team on
+    /// the course, or on any course, fastest first.
+    pub fn leaderboard(&self, course: Option<&str>, count: usize) -> Vec<&RunRecord> {
+        let runs = self
+            .records
+            .iter()
+            .filter(|record| course.is_none() || record.course.as_deref() == course);
+
+        let mut best: HashMap<Runner, &RunRecord> = HashMap::new();
+        for record in runs {
+            let runner = match &record.team {
+                Some(team) => Runner::Team(team),
+                None => Runner::Player(record.player),
+            };
+
+            best.entry(runner)
+                .and_modify(|other| {
+                    if record.time < other.time {
+                        *other = record;
+                    }
+                })
+                .or_insert(record);
+        }
+
+        let mut best = best.into_values().collect::<Vec<_>>();
+        best.sort_by_key(|record| record.time);
+        best.truncate(count);
+
+        best
+    }
+
+    /// The best relay of every team, fastest first.
+    pub fn best_relays(&self, count: usize) -> Vec<&RunRecord> {
+        let mut best: HashMap<&str, &RunRecord> = HashMap::new();
+
+        for record in &self.records {
+            let Some(team) = &record.team else {
+                continue;
+            };
+
+            best.entry(team)
+                .and_modify(|other| {
+                    if record.time < other.time {
+                        *other = record;
+                    }
+                })
+                .or_insert(record);
+        }
+
+        let mut best = best.into_values().collect::<Vec<_>>();
+        best.sort_by_key(|record| record.time);
+        best.truncate(count);
+
+        best
+    }
+
+    pub fn best_relay(&self, team: &str) -> Option<&RunRecord> {
+        self.records
+            .iter()
+            .filter(|record| record.team.as_deref() == Some(team))
+            .min_by_key(|record| record.time)
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.as_millis();
+
+    format!(
+        "{}:{:02}.{:02}",
+        millis / 60_000,
+        millis / 1000 % 60,
+        millis / 10 % 100
+    )
+}
@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use valence::prelude::*;
+
+/// A relay race of a team. Every member runs one leg of the course in turn,
+/// and only the active runner's progress counts.
+pub struct Relay {
+    pub team: String,
+    runners: Vec<Uuid>,
+    /// How many platforms every leg is long.
+    leg_length: i32,
+    active: usize,
+    /// Whether the active runner still waits for the platform their leg
+    /// starts on to be generated.
+    starting: bool,
+    legs: Vec<Duration>,
+}
+
+impl Relay {
+    pub fn new(team: String, runners: Vec<Uuid>, leg_length: i32) -> Self {
+        Self {
+            team,
+            runners,
+            leg_length,
+            active: 0,
+            starting: false,
+            legs: Vec::new(),
+        }
+    }
+
+    pub fn runner(&self) -> Uuid {
+        self.runners[self.active]
+    }
+
+    pub fn runners(&self) -> &[Uuid] {
+        &self.runners
+    }
+
+    pub fn is_waiting(&self, player_id: &Uuid) -> bool {
+        self.runners.contains(player_id) && (self.starting || self.runner() != *player_id)
+    }
+
+    pub fn is_starting(&self) -> bool {
+        self.starting
+    }
+
+    /// The active runner is off.
+    pub fn started(&mut self) {
+        self.starting = false;
+    }
+
+    pub fn leg(&self) -> usize {
+        self.active
+    }
+
+    pub fn legs(&self) -> usize {
+        self.runners.len()
+    }
+
+    /// The platform the active runner starts from.
+    pub fn leg_start(&self) -> i32 {
+        self.active as i32 * self.leg_length
+    }
+
+    /// The platform the active runner hands off at.
+    pub fn leg_end(&self) -> i32 {
+        (self.active as i32 + 1) * self.leg_length
+    }
+
+    /// Records the active runner's time and moves on to the next one, who
+    /// has to be started. Returns the next runner, or `None` once the last
+    /// leg is done.
+    pub fn hand_off(&mut self, time: Duration) -> Option<Uuid> {
+        self.legs.push(time);
+
+        if self.active + 1 >= self.runners.len() {
+            return None;
+        }
+
+        self.active += 1;
+        self.starting = true;
+        Some(self.runner())
+    }
+
+    pub fn total(&self) -> Duration {
+        self.legs.iter().sum()
+    }
+}
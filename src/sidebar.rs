@@ -0,0 +1,147 @@
+use std::borrow::Cow;
+
+use valence::prelude::*;
+use valence::protocol::packets::play::scoreboard_display_s2c::ScoreboardPosition;
+use valence::protocol::packets::play::scoreboard_objective_update_s2c::{
+    ObjectiveMode, ObjectiveRenderType,
+};
+use valence::protocol::packets::play::scoreboard_player_update_s2c::ScoreboardPlayerUpdateAction;
+use valence::protocol::packets::play::team_s2c::{
+    CollisionRule, Mode, NameTagVisibility, TeamColor, TeamFlags,
+};
+use valence::protocol::packets::play::{
+    ScoreboardDisplayS2c, ScoreboardObjectiveUpdateS2c, ScoreboardPlayerUpdateS2c, TeamS2c,
+};
+use valence::protocol::{VarInt, WritePacket};
+
+const OBJECTIVE: &str = "parkour";
+
+/// The sidebar can show at most 15 lines.
+const MAX_LINES: usize = 15;
+
+/// A per-client sidebar. Every line is a fake score entry whose text is the
+/// prefix of a team only that entry belongs to, so changing a line is a single
+/// team update instead of removing and re-adding the score.
+#[derive(Component, Default)]
+pub struct Sidebar {
+    created: bool,
+    lines: Vec<Text>,
+}
+
+fn entry_name(line: usize) -> String {
+    // Invisible, unique names made of formatting codes.
+    format!("§{:x}§r", line)
+}
+
+fn team_name(line: usize) -> String {
+    format!("sidebar_{line}")
+}
+
+impl Sidebar {
+    pub fn update(&mut self, client: &mut Client, title: Text, lines: Vec<Text>) {
+        let lines = lines.into_iter().take(MAX_LINES).collect::<Vec<_>>();
+
+        if !self.created {
+            client.write_packet(&ScoreboardObjectiveUpdateS2c {
+                objective_name: OBJECTIVE,
+                mode: ObjectiveMode::Create {
+                    objective_display_name: title,
+                    render_type: ObjectiveRenderType::Integer,
+                },
+            });
+            client.write_packet(&ScoreboardDisplayS2c {
+                position: ScoreboardPosition::Sidebar,
+                score_name: OBJECTIVE,
+            });
+
+            self.created = true;
+        }
+
+        let score = |line: usize| VarInt((lines.len() - line) as i32);
+
+        for (i, line) in lines.iter().enumerate() {
+            let entry = entry_name(i);
+            let team = team_name(i);
+
+            match self.lines.get(i) {
+                Some(old) if old == line => continue,
+                Some(_) => {
+                    client.write_packet(&TeamS2c {
+                        team_name: &team,
+                        mode: Mode::UpdateTeamInfo {
+                            team_display_name: Cow::Owned(Text::default()),
+                            friendly_flags: TeamFlags::new(),
+                            name_tag_visibility: NameTagVisibility::Always,
+                            collision_rule: CollisionRule::Always,
+                            team_color: TeamColor::White,
+                            team_prefix: Cow::Borrowed(line),
+                            team_suffix: Cow::Owned(Text::default()),
+                        },
+                    });
+                }
+                None => {
+                    client.write_packet(&TeamS2c {
+                        team_name: &team,
+                        mode: Mode::CreateTeam {
+                            team_display_name: Cow::Owned(Text::default()),
+                            friendly_flags: TeamFlags::new(),
+                            name_tag_visibility: NameTagVisibility::Always,
+                            collision_rule: CollisionRule::Always,
+                            team_color: TeamColor::White,
+                            team_prefix: Cow::Borrowed(line),
+                            team_suffix: Cow::Owned(Text::default()),
+                            entities: vec![&entry],
+                        },
+                    });
+                }
+            }
+        }
+
+        // Scores only need to be resent when the number of lines changes.
+        if lines.len() != self.lines.len() {
+            for i in 0..lines.len() {
+                client.write_packet(&ScoreboardPlayerUpdateS2c {
+                    entity_name: &entry_name(i),
+                    action: ScoreboardPlayerUpdateAction::Update {
+                        objective_name: OBJECTIVE,
+                        objective_score: score(i),
+                    },
+                });
+            }
+
+            for i in lines.len()..self.lines.len() {
+                client.write_packet(&ScoreboardPlayerUpdateS2c {
+                    entity_name: &entry_name(i),
+                    action: ScoreboardPlayerUpdateAction::Remove {
+                        objective_name: OBJECTIVE,
+                    },
+                });
+                client.write_packet(&TeamS2c {
+                    team_name: &team_name(i),
+                    mode: Mode::RemoveTeam,
+                });
+            }
+        }
+
+        self.lines = lines;
+    }
+
+    pub fn hide(&mut self, client: &mut Client) {
+        if !self.created {
+            return;
+        }
+
+        for i in 0..self.lines.len() {
+            client.write_packet(&TeamS2c {
+                team_name: &team_name(i),
+                mode: Mode::RemoveTeam,
+            });
+        }
+        client.write_packet(&ScoreboardObjectiveUpdateS2c {
+            objective_name: OBJECTIVE,
+            mode: ObjectiveMode::Remove,
+        });
+
+        *self = Self::default();
+    }
+}
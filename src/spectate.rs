@@ -0,0 +1,16 @@
+use valence::entity::EntityId;
+use valence::prelude::*;
+use valence::protocol::packets::play::SetCameraEntityS2c;
+use valence::protocol::{VarInt, WritePacket};
+
+/// A player watching another player's run through their camera.
+#[derive(Component)]
+pub struct Spectating(pub Entity);
+
+/// Makes the client see through the eyes of the entity. Setting it back to
+/// the client's own entity releases the camera again.
+pub fn set_camera(client: &mut Client, entity_id: &EntityId) {
+    client.write_packet(&SetCameraEntityS2c {
+        entity_id: VarInt(entity_id.get()),
+    });
+}
@@ -0,0 +1,79 @@
+use std::{fs::File, io::Write, path::Path};
+
+use crate::{jump::JumpKind, parkour::ParkourCourse};
+
+/// How many platforms are grouped into a single row of the heatmap.
+const HEATMAP_BUCKET: i32 = 50;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JumpStats {
+    pub attempts: u32,
+    pub fails: u32,
+}
+
+impl JumpStats {
+    pub fn record(&mut self, failed: bool) {
+        self.attempts += 1;
+        if failed {
+            self.fails += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &JumpStats) {
+        self.attempts += other.attempts;
+        self.fails += other.fails;
+    }
+
+    pub fn fail_rate(&self) -> Option<f64> {
+        if self.attempts == 0 {
+            return None;
+        }
+
+        Some(self.fails as f64 / self.attempts as f64)
+    }
+}
+
+/// Writes the fail rate of the course as a CSV grid: one row per bucket of
+/// platforms, one column per jump kind.
+pub fn export_heatmap(course: &ParkourCourse, path: &Path) -> std::io::Result<()> {
+    let mut rows: Vec<[JumpStats; JumpKind::TEMPLATES.len()]> = Vec::new();
+
+    for (i, jump) in course.jumps().iter().enumerate() {
+        let Some(column) = JumpKind::TEMPLATES
+            .iter()
+            .position(|kind| *kind == jump.kind)
+        else {
+            continue;
+        };
+
+        let row = i / HEATMAP_BUCKET as usize;
+        if rows.len() <= row {
+            rows.resize(row + 1, Default::default());
+        }
+
+        rows[row][column].merge(&jump.stats);
+    }
+
+    let mut file = File::create(path)?;
+
+    write!(file, "platforms")?;
+    for kind in JumpKind::TEMPLATES {
+        write!(file, ",{}", kind.name())?;
+    }
+    writeln!(file)?;
+
+    for (i, row) in rows.iter().enumerate() {
+        let start = i as i32 * HEATMAP_BUCKET;
+        write!(file, "{}-{}", start, start + HEATMAP_BUCKET - 1)?;
+
+        for stats in row {
+            match stats.fail_rate() {
+                Some(rate) => write!(file, ",{rate:.3}")?,
+                None => write!(file, ",")?,
+            }
+        }
+        writeln!(file)?;
+    }
+
+    Ok(())
+}
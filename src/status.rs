@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use serde::Serialize;
+use valence::prelude::{Resource, Uuid};
+
+/// What the outside world gets to know about a single course instance.
+#[derive(Debug, Clone, Serialize)]
+pub struct InstanceStatus {
+    pub name: String,
+    pub difficulty: &'static str,
+    pub players: usize,
+    pub platforms: i32,
+    pub generating: bool,
+}
+
+/// How far a player on a course has made it.
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerStatus {
+    pub name: String,
+    pub id: Uuid,
+    pub course: String,
+    pub platform: i32,
+}
+
+/// The best finished run of a player, or the best relay of a team.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunStatus {
+    /// The name of the player, or of the team for relays.
+    pub name: String,
+    /// For relays, the player who ran the last leg.
+    pub id: Uuid,
+    pub relay: bool,
+    pub time_ms: u64,
+    pub falls: u32,
+    pub platforms: i32,
+}
+
+/// How the generation of a single copy of a course is going.
+#[derive(Debug, Clone, Serialize)]
+pub struct GenerationStatus {
+    pub course: String,
+    pub seed: u64,
+    pub platforms: i32,
+    pub generating: bool,
+    /// Jump attempts and fails on the course so far.
+    pub attempts: u32,
+    pub fails: u32,
+}
+
+#[derive(Default)]
+struct Snapshot {
+    instances: Vec<InstanceStatus>,
+    /// Sorted by platform, furthest first.
+    players: Vec<PlayerStatus>,
+    /// Across all courses, sorted by time, fastest first.
+    leaderboard: Vec<RunStatus>,
+    /// The same for every course on its own.
+    course_leaderboards: HashMap<String, Vec<RunStatus>>,
+    generation: Vec<GenerationStatus>,
+}
+
+/// A snapshot of the server refreshed every tick, shared with code running
+/// outside of the ECS such as the network callbacks.
+#[derive(Resource, Clone, Default)]
+pub struct ServerStatus(Arc<RwLock<Snapshot>>);
+
+impl ServerStatus {
+    pub fn set(
+        &self,
+        instances: Vec<InstanceStatus>,
+        mut players: Vec<PlayerStatus>,
+        generation: Vec<GenerationStatus>,
+    ) {
+        players.sort_by(|a, b| b.platform.cmp(&a.platform));
+
+        let mut snapshot = self.0.write().unwrap();
+        snapshot.instances = instances;
+        snapshot.players = players;
+        snapshot.generation = generation;
+    }
+
+    /// Only changes when a run is recorded, so it's set separately. Without
+    /// a course, sets the leaderboard across all of them.
+    pub fn set_leaderboard(&self, course: Option<&str>, mut leaderboard: Vec<RunStatus>) {
+        leaderboard.sort_by_key(|run| run.time_ms);
+
+        let mut snapshot = self.0.write().unwrap();
+        match course {
+            Some(course) => {
+                snapshot
+                    .course_leaderboards
+                    .insert(course.to_owned(), leaderboard);
+            }
+            None => snapshot.leaderboard = leaderboard,
+        }
+    }
+
+    pub fn instances(&self) -> Vec<InstanceStatus> {
+        self.0.read().unwrap().instances.clone()
+    }
+
+    pub fn players(&self) -> Vec<PlayerStatus> {
+        self.0.read().unwrap().players.clone()
+    }
+
+    /// The `count` players furthest along their course.
+    pub fn top_players(&self, count: usize) -> Vec<PlayerStatus> {
+        self.0
+            .read()
+            .unwrap()
+            .players
+            .iter()
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    pub fn leaderboard(&self, course: Option<&str>) -> Vec<RunStatus> {
+        let snapshot = self.0.read().unwrap();
+
+        match course {
+            Some(course) => snapshot
+                .course_leaderboards
+                .get(course)
+                .cloned()
+                .unwrap_or_default(),
+            None => snapshot.leaderboard.clone(),
+        }
+    }
+
+    pub fn generation(&self) -> Vec<GenerationStatus> {
+        self.0.read().unwrap().generation.clone()
+    }
+}
@@ -0,0 +1,121 @@
+use valence::prelude::*;
+
+const TEAM_COLORS: [Color; 8] = [
+    Color::RED,
+    Color::BLUE,
+    Color::GREEN,
+    Color::GOLD,
+    Color::AQUA,
+    Color::LIGHT_PURPLE,
+    Color::YELLOW,
+    Color::DARK_GREEN,
+];
+
+/// Players sharing their checkpoints on a course.
+pub struct Team {
+    pub name: String,
+    pub color: Color,
+    pub members: Vec<Uuid>,
+    /// Whether every member has reached the end of the course.
+    pub finished: bool,
+}
+
+#[derive(Default)]
+pub struct Teams {
+    teams: Vec<Team>,
+}
+
+impl Teams {
+    pub fn iter(&self) -> impl Iterator<Item = &Team> {
+        self.teams.iter()
+    }
+
+    pub fn get(&self, player_id: &Uuid) -> Option<&Team> {
+        self.teams
+            .iter()
+            .find(|team| team.members.contains(player_id))
+    }
+
+    pub fn get_mut(&mut self, player_id: &Uuid) -> Option<&mut Team> {
+        self.teams
+            .iter_mut()
+            .find(|team| team.members.contains(player_id))
+    }
+
+    /// Everyone on the player's team except the player.
+    pub fn teammates(&self, player_id: &Uuid) -> Vec<Uuid> {
+        self.get(player_id)
+            .map(|team| {
+                team.members
+                    .iter()
+                    .filter(|member| *member != player_id)
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Creates a team with the player as its only member.
+    pub fn create(&mut self, name: &str, player_id: Uuid) -> Result<&Team, String> {
+        // Relays are stored under the team name, in a comma separated file
+        if name.contains(',') {
+            return Err("Team names can't contain commas.".to_owned());
+        }
+
+        if self.teams.iter().any(|team| team.name == name) {
+            return Err(format!("There already is a team called {name}."));
+        }
+
+        let color = TEAM_COLORS
+            .iter()
+            .find(|color| self.teams.iter().all(|team| team.color != **color))
+            .copied()
+            .ok_or_else(|| "There are too many teams on this course.".to_owned())?;
+
+        self.leave(&player_id);
+        self.teams.push(Team {
+            name: name.to_owned(),
+            color,
+            members: vec![player_id],
+            finished: false,
+        });
+
+        Ok(self.teams.last().unwrap())
+    }
+
+    pub fn join(&mut self, name: &str, player_id: Uuid) -> Result<&Team, String> {
+        if !self.teams.iter().any(|team| team.name == name) {
+            return Err(format!("There is no team called {name}."));
+        }
+
+        self.leave(&player_id);
+
+        let team = self
+            .teams
+            .iter_mut()
+            .find(|team| team.name == name)
+            .unwrap();
+        team.members.push(player_id);
+
+        Ok(team)
+    }
+
+    /// Removes the player from their team, disbanding it if it's now empty.
+    /// Returns the name of the team they left.
+    pub fn leave(&mut self, player_id: &Uuid) -> Option<String> {
+        let team = self.get_mut(player_id)?;
+        team.members.retain(|member| member != player_id);
+        let name = team.name.clone();
+
+        self.teams.retain(|team| !team.members.is_empty());
+
+        Some(name)
+    }
+
+    /// Lets every team finish the course again.
+    pub fn reset(&mut self) {
+        for team in &mut self.teams {
+            team.finished = false;
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use valence::anvil::AnvilLevel;
+use valence::prelude::*;
+
+/// The chunks around the spawn as loaded from the world, shared by the
+/// private copies of courses so that each doesn't load them from disk again.
+#[derive(Resource, Default)]
+pub struct TerrainCache {
+    chunks: HashMap<ChunkPos, UnloadedChunk>,
+}
+
+impl TerrainCache {
+    /// The chunks loaded before anyone joins a course, as a square around the
+    /// origin.
+    pub fn preload_area(radius: i32) -> impl Iterator<Item = ChunkPos> {
+        (-radius..radius).flat_map(move |z| (-radius..radius).map(move |x| ChunkPos::new(x, z)))
+    }
+
+    fn in_preload_area(pos: ChunkPos, radius: i32) -> bool {
+        (-radius..radius).contains(&pos.x) && (-radius..radius).contains(&pos.z)
+    }
+
+    /// Keeps a copy of a chunk that was just loaded, before anything is built
+    /// in it.
+    pub fn store(&mut self, pos: ChunkPos, layer: &mut ChunkLayer, radius: i32) {
+        if !Self::in_preload_area(pos, radius) || self.chunks.contains_key(&pos) {
+            return;
+        }
+
+        // Chunks can only be copied out of a layer by taking them out
+        let Some(chunk) = layer.insert_chunk(pos, UnloadedChunk::new()) else {
+            return;
+        };
+        layer.insert_chunk(pos, chunk.clone());
+
+        self.chunks.insert(pos, chunk);
+    }
+
+    /// Fills the preload area of a new course layer from the cache, if it has
+    /// all of it. Returns false if the chunks still have to be loaded.
+    pub fn fill(&self, layer: &mut ChunkLayer, level: &mut AnvilLevel, radius: i32) -> bool {
+        if !Self::preload_area(radius).all(|pos| self.chunks.contains_key(&pos)) {
+            return false;
+        }
+
+        for pos in Self::preload_area(radius) {
+            layer.insert_chunk(pos, self.chunks[&pos].clone());
+            // Already there, so the level must neither load nor unload it
+            level.ignored_chunks.insert(pos);
+        }
+
+        true
+    }
+}